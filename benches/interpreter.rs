@@ -0,0 +1,53 @@
+use rilox::lox::Lox;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 10;
+
+const FIB: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+fib(20);
+";
+
+const LOOP: &str = "
+var sum = 0;
+for (var i = 0; i < 100000; i = i + 1) {
+    sum = sum + i;
+}
+";
+
+const CONCAT: &str = "
+var text = \"\";
+for (var i = 0; i < 10000; i = i + 1) {
+    text = text + \"ab\";
+}
+";
+
+fn bench(lox: &mut Lox, name: &str, source: &str) {
+    let mut fastest = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        lox.reset();
+        let start = Instant::now();
+        lox.run(String::from(source), true);
+        let elapsed = start.elapsed();
+        fastest = fastest.min(elapsed);
+        total += elapsed;
+    }
+    println!(
+        "{:<8} mean {:>10.3?}  fastest {:>10.3?}  ({} runs)",
+        name,
+        total / RUNS,
+        fastest,
+        RUNS
+    );
+}
+
+fn main() {
+    let mut lox = Lox::new();
+    bench(&mut lox, "fib", FIB);
+    bench(&mut lox, "loop", LOOP);
+    bench(&mut lox, "concat", CONCAT);
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    // Scanning and parsing, the program doesn't run.
+    Error,
+    Warning,
+    RuntimeError,
+}
+
+// Something reported while running a program, printed by the CLI and returned from `Lox::run`
+// for hosts that want to show them themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub line: u64,
+    // Where on the line, counted in characters from 1. None when the token has no place on its
+    // line, like the end of the input, a string spanning lines or a scan error.
+    pub column: Option<usize>,
+    // "at 'x'", "at end" or empty, as it is printed.
+    pub location: String,
+    // The source line of a runtime error with the token underlined, None when the line isn't
+    // known, like for a token the interpreter made up.
+    pub context: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            DiagnosticKind::Error => write!(
+                f,
+                "[line {}] Error {}: {}",
+                self.line, self.location, self.message
+            ),
+            DiagnosticKind::Warning => write!(
+                f,
+                "[line {}] Warning {}: {}",
+                self.line, self.location, self.message
+            ),
+            DiagnosticKind::RuntimeError => {
+                write!(f, "{}\n[line {}]", self.message, self.line)?;
+                match &self.context {
+                    Some(context) => write!(f, "\n{}", context),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
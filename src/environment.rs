@@ -1,12 +1,25 @@
-use crate::loxvalue::LoxValue;
+use crate::loxvalue::{InstanceValue, LoxValue};
+use crate::natives::edit_distance;
+use crate::runtime::Runtime;
 use crate::token::Token;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 use std::rc::Rc;
 
 pub struct Environment {
     pub(crate) enclosing: Option<Rc<Environment>>,
     pub(crate) values: RefCell<HashMap<String, LoxValue>>,
+    // Names of the values above declared with const, assigning to them is an error. The parser
+    // already rejects that within one parse, this covers the REPL and eval.
+    pub(crate) constants: RefCell<HashSet<String>>,
+    pub(crate) runtime: Rc<Runtime>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
 }
 
 impl Clone for Environment {
@@ -14,20 +27,30 @@ impl Clone for Environment {
         Environment {
             enclosing: self.enclosing.clone(),
             values: self.values.clone(),
+            constants: self.constants.clone(),
+            runtime: Rc::clone(&self.runtime),
         }
     }
 
     fn clone_from(&mut self, source: &Self) {
         self.values = source.values.clone();
+        self.constants = source.constants.clone();
         self.enclosing = source.enclosing.clone();
+        self.runtime = Rc::clone(&source.runtime);
     }
 }
 
 impl Environment {
     pub fn new() -> Self {
+        Environment::new_with_runtime(Rc::new(Runtime::default()))
+    }
+
+    pub fn new_with_runtime(runtime: Rc<Runtime>) -> Self {
         Environment {
             enclosing: None,
             values: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashSet::new()),
+            runtime,
         }
     }
 
@@ -35,23 +58,72 @@ impl Environment {
         Environment {
             enclosing: Some(env.clone()),
             values: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashSet::new()),
+            runtime: Rc::clone(&env.runtime),
+        }
+    }
+
+    pub(crate) fn globals(env: &Rc<Environment>) -> Rc<Environment> {
+        match &env.enclosing {
+            None => Rc::clone(env),
+            Some(parent) => Environment::globals(parent),
         }
     }
 
+    // A var declared again under the name of a constant is a variable from then on.
     pub(crate) fn define(&self, key: String, value: LoxValue) {
+        self.constants.borrow_mut().remove(&key);
+        self.values.borrow_mut().insert(key, value);
+    }
+
+    pub(crate) fn define_constant(&self, key: String, value: LoxValue) {
+        self.constants.borrow_mut().insert(key.clone());
         self.values.borrow_mut().insert(key, value);
     }
 
     pub(crate) fn get(&self, name: &Token) -> Result<LoxValue, String> {
-        match self.values.borrow_mut().get(&*name.lexeme) {
-            None => match &self.enclosing {
+        match self.lookup(&name.lexeme) {
+            Some(value) => Ok(value),
+            None => match self.closest_name(&name.lexeme) {
+                Some(closest) => Err(format!(
+                    "Undefined variable '{}'. Did you mean '{}'?",
+                    name.lexeme, closest
+                )),
                 None => Err(format!("Undefined variable '{}'.", name.lexeme)),
-                Some(parent) => parent.get(name),
             },
-            Some(a) => Ok(a.clone()),
         }
     }
 
+    fn lookup(&self, name: &str) -> Option<LoxValue> {
+        match self.values.borrow().get(name) {
+            None => self.enclosing.as_ref()?.lookup(name),
+            Some(a) => Some(a.clone()),
+        }
+    }
+
+    // The visible name fewest edits away from `name`, when it is within a third of its length.
+    fn closest_name(&self, name: &str) -> Option<String> {
+        let limit = name.chars().count() / 3;
+        let mut closest: Option<(usize, String)> = None;
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            for candidate in env.values.borrow().keys() {
+                let distance = edit_distance(name, candidate);
+                let better = match &closest {
+                    None => distance <= limit,
+                    // Ties go to the name that sorts first, so the suggestion doesn't depend on
+                    // the hash map order.
+                    Some(best) => (distance, candidate) < (best.0, &best.1),
+                };
+                if better {
+                    closest = Some((distance, candidate.clone()));
+                }
+            }
+            scope = env.enclosing.as_deref();
+        }
+        closest.map(|(_, name)| name)
+    }
+
     pub(crate) fn get_by_string(&self, name: String) -> Result<LoxValue, String> {
         match self.values.borrow_mut().get(&*name) {
             None => match &self.enclosing {
@@ -63,9 +135,12 @@ impl Environment {
     }
 
     pub(crate) fn assign(&self, name: &Token, value: LoxValue) -> Result<(), (String, Token)> {
-        let lexeme = &*name.lexeme;
-        if self.values.borrow_mut().contains_key(lexeme) {
-            self.values.borrow_mut().insert(String::from(lexeme), value);
+        if let Some(slot) = self.values.borrow_mut().get_mut(&name.lexeme) {
+            if self.constants.borrow().contains(&name.lexeme) {
+                let msg = format!("Can't assign to the constant '{}'.", name.lexeme);
+                return Err((msg, name.clone()));
+            }
+            *slot = value;
             return Ok(());
         }
         match &self.enclosing {
@@ -76,4 +151,44 @@ impl Environment {
             Some(parent) => parent.assign(name, value),
         }
     }
+
+    // Scopes from the globals inward, each variable on a line with instance fields below it.
+    pub(crate) fn dump(&self) -> String {
+        let mut scopes = vec![self];
+        let mut current = self;
+        while let Some(parent) = &current.enclosing {
+            scopes.push(parent);
+            current = parent;
+        }
+
+        let mut out = String::new();
+        for (depth, scope) in scopes.iter().rev().enumerate() {
+            writeln!(out, "{}scope {}", "  ".repeat(depth), depth).unwrap();
+            dump_values(&mut out, &scope.values.borrow(), depth + 1, &mut vec![]);
+        }
+        out
+    }
+}
+
+fn dump_values(
+    out: &mut String,
+    values: &HashMap<String, LoxValue>,
+    depth: usize,
+    // Instances being dumped, a field pointing back to one is not expanded again.
+    path: &mut Vec<*const InstanceValue>,
+) {
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    for name in names {
+        let value = &values[name];
+        writeln!(out, "{}{} = {}", "  ".repeat(depth), name, value).unwrap();
+        if let LoxValue::Instance(instance) = value {
+            let pointer = Rc::as_ptr(instance);
+            if !path.contains(&pointer) {
+                path.push(pointer);
+                dump_values(out, &instance.fields.borrow(), depth + 1, path);
+                path.pop();
+            }
+        }
+    }
 }
@@ -1,28 +1,50 @@
 use crate::environment::Environment;
-use crate::loxvalue::LoxValue;
+use crate::loxvalue::{Class, InstanceValue, LoxValue};
+use crate::module;
+use crate::runtime::Depth;
+use crate::sexpr::{write_token, write_value};
+use crate::stmt::{Function, Stmt};
 use crate::token::Token;
 use crate::tokentype::TokenType;
+use crate::visitor::Node;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+// Longest string `*` repeats up to, in bytes, so a huge count is an error instead of an abort.
+const MAX_REPEAT_LENGTH: usize = 1 << 28;
+
 pub trait Expr {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)>;
     fn kind(&self) -> Kind;
+    // For `sexpr::to_sexpr`, in the form `sexpr::from_sexpr` reads back.
+    fn to_sexpr(&self) -> String;
+    // The sub-expressions, for visitors.
+    fn children(&self) -> Vec<Node<'_>> {
+        Vec::new()
+    }
 }
 
 pub enum Kind {
-    Binary,
+    Binary(Token),
     Grouping,
-    Literal,
+    Literal(LoxValue),
     Unary,
     Variable(Token),
     NoOp,
-    Assign,
+    Assign(Token),
     Logical,
-    Call,
+    Call(Token),
     Get(Token, Rc<dyn Expr>),
+    OptionalGet,
     Set,
     This,
     Super,
+    Import,
+    In,
+    Conditional,
+    Object,
+    Lambda,
 }
 
 pub struct Binary {
@@ -33,67 +55,238 @@ pub struct Binary {
 
 impl Expr for Binary {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let _depth = enter(&env, &self.operator)?;
         let left = self.left.evaluate(Rc::clone(&env))?;
         let right = self.right.evaluate(Rc::clone(&env))?;
-        let token = self.operator.clone();
-        match self.operator.token_type {
+        // Only clone the operator token when there is an error to report.
+        let error = |msg: &str| Err((String::from(msg), self.operator.clone()));
+        let result = match self.operator.token_type {
             TokenType::BangEqual => Ok(is_equal(left, right, true)),
             TokenType::EqualEqual => Ok(is_equal(left, right, false)),
-            TokenType::Greater => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => Ok(LoxValue::Bool(a > b)),
-                _ => Err((String::from("Can only compare two numbers."), token)),
-            },
-            TokenType::GreaterEqual => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => Ok(LoxValue::Bool(a >= b)),
-                _ => Err((String::from("Can only compare two numbers."), token)),
-            },
-            TokenType::Less => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => Ok(LoxValue::Bool(a < b)),
-                _ => Err((String::from("Can only compare two numbers."), token)),
-            },
-            TokenType::LessEqual => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => Ok(LoxValue::Bool(a <= b)),
-                _ => Err((String::from("Can only compare two numbers."), token)),
-            },
-            TokenType::Minus => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => {
-                    Ok(LoxValue::Number(a.clone() - b.clone()))
-                }
-                _ => Err((String::from("Can only subtract two numbers."), token)),
-            },
+            TokenType::Greater => compare(left, right, PartialOrd::gt, PartialOrd::gt)
+                .map_or_else(|| error("Can only compare two numbers."), Ok),
+            TokenType::GreaterEqual => compare(left, right, PartialOrd::ge, PartialOrd::ge)
+                .map_or_else(|| error("Can only compare two numbers."), Ok),
+            TokenType::Less => compare(left, right, PartialOrd::lt, PartialOrd::lt)
+                .map_or_else(|| error("Can only compare two numbers."), Ok),
+            TokenType::LessEqual => compare(left, right, PartialOrd::le, PartialOrd::le)
+                .map_or_else(|| error("Can only compare two numbers."), Ok),
+            TokenType::Minus => arithmetic(left, right, i64::checked_sub, |a, b| a - b)
+                .map_or_else(|| error("Can only subtract two numbers."), Ok),
             TokenType::Plus => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => {
-                    Ok(LoxValue::Number(a.clone() + b.clone()))
+                (LoxValue::String(mut a), LoxValue::String(b)) => {
+                    // The left operand is already an owned copy, append to it in place.
+                    a.push_str(&b);
+                    Ok(LoxValue::String(a))
                 }
-                (LoxValue::String(a), LoxValue::String(b)) => {
-                    Ok(LoxValue::String(format!("{}{}", a, b)))
-                }
-                _ => Err((
-                    String::from("Can only add two numbers or concatenate two strings."),
-                    token,
-                )),
+                (left, right) => arithmetic(left, right, i64::checked_add, |a, b| a + b)
+                    .map_or_else(
+                        || error("Can only add two numbers or concatenate two strings."),
+                        Ok,
+                    ),
             },
-            TokenType::Slash => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => {
-                    Ok(LoxValue::Number(a.clone() / b.clone()))
-                }
-                _ => Err((String::from("Can only divide two numbers."), token)),
+            // Division always gives a float, 1 / 2 is 0.5.
+            TokenType::Slash => match (left.as_number(), right.as_number()) {
+                (Some(a), Some(b)) => Ok(LoxValue::Number(a / b)),
+                _ => error("Can only divide two numbers."),
             },
             TokenType::Star => match (left, right) {
-                (LoxValue::Number(a), LoxValue::Number(b)) => {
-                    Ok(LoxValue::Number(a.clone() * b.clone()))
+                (LoxValue::String(a), count @ (LoxValue::Int(_) | LoxValue::Number(_))) => {
+                    match count.as_index() {
+                        Some(count) => match a.len().checked_mul(count) {
+                            Some(length) if length <= MAX_REPEAT_LENGTH => {
+                                Ok(LoxValue::String(a.repeat(count)))
+                            }
+                            _ => error("String repetition too large."),
+                        },
+                        None => {
+                            error("Can only repeat a string a non-negative whole number of times.")
+                        }
+                    }
                 }
-                _ => Err((String::from("Can only multiply two numbers."), token)),
+                (left, right) => arithmetic(left, right, i64::checked_mul, |a, b| a * b)
+                    .map_or_else(|| error("Can only multiply two numbers."), Ok),
             },
-            _ => Err((String::from("Unknown binary operation."), token)),
+            _ => error("Unknown binary operation."),
+        };
+        if let Ok(LoxValue::Number(value)) = result {
+            if !value.is_finite() && env.runtime.strict_math.get() {
+                return if value.is_nan() {
+                    error("Arithmetic produced NaN.")
+                } else {
+                    error("Arithmetic produced infinity.")
+                };
+            }
+        }
+        result
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.left.as_ref()),
+            Node::Expr(self.right.as_ref()),
+        ]
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Binary(self.operator.clone())
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(binary {} {} {})",
+            self.left.to_sexpr(),
+            write_token(&self.operator),
+            self.right.to_sexpr()
+        )
+    }
+}
+
+pub struct In {
+    pub(crate) element: Rc<dyn Expr>,
+    pub(crate) keyword: Token,
+    pub(crate) collection: Rc<dyn Expr>,
+}
+
+impl Expr for In {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let element = self.element.evaluate(Rc::clone(&env))?;
+        let collection = self.collection.evaluate(env)?;
+        let error = |msg: &str| Err((String::from(msg), self.keyword.clone()));
+        match (element, collection) {
+            (LoxValue::String(a), LoxValue::String(b)) => Ok(LoxValue::Bool(b.contains(&a))),
+            // Instances are the closest thing to a map, check for the field.
+            (LoxValue::String(a), LoxValue::Instance(b)) => {
+                Ok(LoxValue::Bool(b.fields.borrow().contains_key(&a)))
+            }
+            (_, LoxValue::String(_)) => error("Can only look for a string in a string."),
+            (_, LoxValue::Instance(_)) => error("Field name must be a string."),
+            _ => error("Can only look in a string or an instance."),
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.element.as_ref()),
+            Node::Expr(self.collection.as_ref()),
+        ]
+    }
+
     fn kind(&self) -> Kind {
-        Kind::Binary
+        Kind::In
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(in {} {} {})",
+            self.element.to_sexpr(),
+            write_token(&self.keyword),
+            self.collection.to_sexpr()
+        )
     }
 }
 
+pub struct Conditional {
+    pub(crate) condition: Rc<dyn Expr>,
+    pub(crate) then_branch: Rc<dyn Expr>,
+    pub(crate) else_branch: Rc<dyn Expr>,
+}
+
+impl Expr for Conditional {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        match is_truthy(self.condition.evaluate(Rc::clone(&env))?, false)? {
+            LoxValue::Bool(true) => self.then_branch.evaluate(env),
+            _ => self.else_branch.evaluate(env),
+        }
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.condition.as_ref()),
+            Node::Expr(self.then_branch.as_ref()),
+            Node::Expr(self.else_branch.as_ref()),
+        ]
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Conditional
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(conditional {} {} {})",
+            self.condition.to_sexpr(),
+            self.then_branch.to_sexpr(),
+            self.else_branch.to_sexpr()
+        )
+    }
+}
+
+pub struct Object {
+    pub(crate) brace: Token,
+    pub(crate) fields: Vec<(Token, Rc<dyn Expr>)>,
+}
+
+impl Expr for Object {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let mut fields = HashMap::new();
+        for (name, value) in &self.fields {
+            fields.insert(name.lexeme.clone(), value.evaluate(Rc::clone(&env))?);
+        }
+        new_object(fields, &env)
+            .map(LoxValue::Instance)
+            .map_err(|msg| (msg, self.brace.clone()))
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        self.fields
+            .iter()
+            .map(|(_, value)| Node::Expr(value.as_ref()))
+            .collect()
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Object
+    }
+
+    fn to_sexpr(&self) -> String {
+        let mut sexpr = format!("(object {}", write_token(&self.brace));
+        for (name, value) in &self.fields {
+            sexpr.push_str(&format!(
+                " (field {} {})",
+                write_token(name),
+                value.to_sexpr()
+            ));
+        }
+        sexpr.push(')');
+        sexpr
+    }
+}
+
+// An instance of a class of its own named Object, like `#{}` gives.
+pub(crate) fn new_object(
+    fields: HashMap<String, LoxValue>,
+    env: &Environment,
+) -> Result<Rc<InstanceValue>, String> {
+    let allocation = env.runtime.allocate()?;
+    let class = Class {
+        name: String::from("Object"),
+        arity: 0,
+        methods: RefCell::new(HashMap::new()),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+        statics: RefCell::new(HashMap::new()),
+        static_methods: HashMap::new(),
+        super_class: None,
+        runtime: Rc::clone(&env.runtime),
+    };
+    Ok(Rc::new(InstanceValue {
+        class: Rc::new(class),
+        fields: RefCell::new(fields),
+        _allocation: allocation,
+    }))
+}
+
 pub struct Grouping {
     pub(crate) expression: Rc<dyn Expr>,
 }
@@ -103,9 +296,17 @@ impl Expr for Grouping {
         self.expression.evaluate(env)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.expression.as_ref())]
+    }
+
     fn kind(&self) -> Kind {
         Kind::Grouping
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(grouping {})", self.expression.to_sexpr())
+    }
 }
 
 pub struct Literal {
@@ -118,7 +319,11 @@ impl Expr for Literal {
     }
 
     fn kind(&self) -> Kind {
-        Kind::Literal
+        Kind::Literal(self.value.clone())
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!("(literal {})", write_value(&self.value))
     }
 }
 
@@ -129,10 +334,14 @@ pub struct Unary {
 
 impl Expr for Unary {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let _depth = enter(&env, &self.operator)?;
         let right = self.right.evaluate(env)?;
         match self.operator.token_type {
             TokenType::Minus => match right {
-                LoxValue::Number(a) => Ok(LoxValue::Number(-a.clone())),
+                LoxValue::Number(a) => Ok(LoxValue::Number(-a)),
+                LoxValue::Int(a) => Ok(a
+                    .checked_neg()
+                    .map_or(LoxValue::Number(-(a as f64)), LoxValue::Int)),
                 _ => Err((
                     String::from("Only know numbers to minus!"),
                     self.operator.clone(),
@@ -146,9 +355,21 @@ impl Expr for Unary {
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.right.as_ref())]
+    }
+
     fn kind(&self) -> Kind {
         Kind::Unary
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(unary {} {})",
+            write_token(&self.operator),
+            self.right.to_sexpr()
+        )
+    }
 }
 
 pub struct Variable {
@@ -158,7 +379,8 @@ pub struct Variable {
 impl Expr for Variable {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
         match env.get(&self.name) {
-            Ok(val) => Ok(val.clone()),
+            Ok(LoxValue::Lazy(lazy)) => lazy.force(),
+            Ok(val) => Ok(val),
             Err(e) => Err((e, self.name.clone())),
         }
     }
@@ -166,6 +388,10 @@ impl Expr for Variable {
     fn kind(&self) -> Kind {
         Kind::Variable(self.name.clone())
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(variable {})", write_token(&self.name))
+    }
 }
 
 pub struct NoOp {
@@ -180,6 +406,10 @@ impl Expr for NoOp {
     fn kind(&self) -> Kind {
         Kind::NoOp
     }
+
+    fn to_sexpr(&self) -> String {
+        String::from("(noop)")
+    }
 }
 
 pub struct Assign {
@@ -191,13 +421,25 @@ impl Expr for Assign {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
         let value = self.value.evaluate(Rc::clone(&env))?;
         match env.assign(&self.name, value.clone()) {
-            Ok(_) => Ok(value.clone()),
+            Ok(_) => Ok(value),
             Err((msg, _token)) => Err((msg, self.name.clone())),
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.value.as_ref())]
+    }
+
     fn kind(&self) -> Kind {
-        Kind::Assign
+        Kind::Assign(self.name.clone())
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(assign {} {})",
+            write_token(&self.name),
+            self.value.to_sexpr()
+        )
     }
 }
 
@@ -209,6 +451,7 @@ pub struct Logical {
 
 impl Expr for Logical {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let _depth = enter(&env, &self.operator)?;
         let left = self.left.evaluate(Rc::clone(&env))?;
         match self.operator.token_type {
             TokenType::Or => match is_truthy(left.clone(), false)? {
@@ -222,9 +465,25 @@ impl Expr for Logical {
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.left.as_ref()),
+            Node::Expr(self.right.as_ref()),
+        ]
+    }
+
     fn kind(&self) -> Kind {
         Kind::Logical
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(logical {} {} {})",
+            self.left.to_sexpr(),
+            write_token(&self.operator),
+            self.right.to_sexpr()
+        )
+    }
 }
 
 pub struct Call {
@@ -235,31 +494,30 @@ pub struct Call {
 
 impl Expr for Call {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let _depth = enter(&env, &self.paren)?;
+        // The callee first, then the arguments left to right. An argument that errors stops the
+        // call, the ones after it are never evaluated so their side effects don't happen.
         let function = self.callee.evaluate(Rc::clone(&env))?;
         let mut arguments: Vec<LoxValue> = Vec::new();
         for argument in &self.arguments {
             arguments.push(argument.evaluate(Rc::clone(&env))?);
         }
+        if env.runtime.allow_breakpoint.get() {
+            env.runtime.caller.replace(Rc::downgrade(&env));
+        }
         match function {
-            LoxValue::Function(callable) => {
-                if callable.arity != arguments.len() {
-                    Err((
-                        format!(
-                            "Expected {} argument(s) but got {}.",
-                            callable.arity,
-                            arguments.len()
-                        ),
-                        self.paren.clone(),
-                    ))
-                } else {
-                    match callable.call(arguments) {
-                        Ok(a) => Ok(a),
-                        Err((msg, token)) => Err((msg, token.clone())),
-                    }
-                }
-            }
+            LoxValue::Function(callable) => match callable.arity_error(arguments.len()) {
+                Some(msg) => Err((msg, self.paren.clone())),
+                None => match callable.call(arguments) {
+                    Ok(a) => Ok(a),
+                    // Errors without a source line (natives) are reported at the call.
+                    Err((msg, token)) if token.line == 0 => Err((msg, self.paren.clone())),
+                    Err((msg, token)) => Err((msg, token.clone())),
+                },
+            },
             LoxValue::Class(class) => match class.call(arguments) {
                 Ok(a) => Ok(a),
+                Err((msg, token)) if token.line == 0 => Err((msg, self.paren.clone())),
                 Err((msg, token)) => Err((msg, token.clone())),
             },
             _ => Err((
@@ -269,8 +527,32 @@ impl Expr for Call {
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        let mut children = vec![Node::Expr(self.callee.as_ref())];
+        children.extend(
+            self.arguments
+                .iter()
+                .map(|argument| Node::Expr(argument.as_ref())),
+        );
+        children
+    }
+
     fn kind(&self) -> Kind {
-        Kind::Call
+        Kind::Call(self.paren.clone())
+    }
+
+    fn to_sexpr(&self) -> String {
+        let mut sexpr = format!(
+            "(call {} {}",
+            self.callee.to_sexpr(),
+            write_token(&self.paren)
+        );
+        for argument in &self.arguments {
+            sexpr.push(' ');
+            sexpr.push_str(&argument.to_sexpr());
+        }
+        sexpr.push(')');
+        sexpr
     }
 }
 
@@ -284,7 +566,8 @@ impl Expr for Get {
         let object = self.object.evaluate(env)?;
         match object {
             LoxValue::Instance(instance) => instance.get_value(&self.name),
-
+            LoxValue::Module(module) => module.get_value(&self.name),
+            LoxValue::Class(class) => class.get_static(&self.name),
             _ => Err((
                 String::from("Only instances have properties."),
                 self.name.clone(),
@@ -292,9 +575,58 @@ impl Expr for Get {
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.object.as_ref())]
+    }
+
     fn kind(&self) -> Kind {
         Kind::Get(self.name.clone(), Rc::clone(&self.object))
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(get {} {})",
+            self.object.to_sexpr(),
+            write_token(&self.name)
+        )
+    }
+}
+
+pub struct OptionalGet {
+    pub(crate) object: Rc<dyn Expr>,
+    pub(crate) name: Token,
+}
+
+impl Expr for OptionalGet {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let object = self.object.evaluate(env)?;
+        match object {
+            LoxValue::None => Ok(LoxValue::None),
+            LoxValue::Instance(instance) => instance.get_value(&self.name),
+            LoxValue::Module(module) => module.get_value(&self.name),
+            LoxValue::Class(class) => class.get_static(&self.name),
+            _ => Err((
+                String::from("Only instances have properties."),
+                self.name.clone(),
+            )),
+        }
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.object.as_ref())]
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::OptionalGet
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(optional_get {} {})",
+            self.object.to_sexpr(),
+            write_token(&self.name)
+        )
+    }
 }
 
 pub struct Set {
@@ -309,7 +641,15 @@ impl Expr for Set {
         match object {
             LoxValue::Instance(a) => {
                 let value = self.value.evaluate(Rc::clone(&env))?;
-                a.set_value(self.name.lexeme.clone(), value.clone());
+                a.set_property(&self.name, value.clone())?;
+                Ok(value)
+            }
+            LoxValue::Class(class) => {
+                let value = self.value.evaluate(Rc::clone(&env))?;
+                class
+                    .statics
+                    .borrow_mut()
+                    .insert(self.name.lexeme.clone(), value.clone());
                 Ok(value)
             }
             _ => Err((
@@ -319,9 +659,25 @@ impl Expr for Set {
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.object.as_ref()),
+            Node::Expr(self.value.as_ref()),
+        ]
+    }
+
     fn kind(&self) -> Kind {
         Kind::Set
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(set {} {} {})",
+            self.object.to_sexpr(),
+            write_token(&self.name),
+            self.value.to_sexpr()
+        )
+    }
 }
 
 pub struct This {
@@ -339,6 +695,10 @@ impl Expr for This {
     fn kind(&self) -> Kind {
         Kind::This
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(this {})", write_token(&self.keyword))
+    }
 }
 
 pub struct Super {
@@ -351,6 +711,14 @@ impl Expr for Super {
         match env.get_by_string(String::from("super")) {
             Ok(a) => match a {
                 LoxValue::Class(super_class) => {
+                    // Getters first, like on an instance.
+                    if let Some(getter) = super_class.find_getter(&self.method.lexeme) {
+                        let this = env
+                            .get_by_string(String::from("this"))
+                            .map_err(|msg| (msg, self.keyword.clone()))?;
+                        getter.bind(this);
+                        return getter.call(Vec::new());
+                    }
                     match super_class.find_method(self.method.lexeme.clone()) {
                         None => Err((
                             format!("Undefined property'{}'.", self.method.lexeme),
@@ -384,13 +752,69 @@ impl Expr for Super {
     fn kind(&self) -> Kind {
         Kind::Super
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(super {} {})",
+            write_token(&self.keyword),
+            write_token(&self.method)
+        )
+    }
+}
+
+// `\x, y -> x + y`, a function with `return x + y;` as its body.
+pub struct Lambda {
+    pub(crate) function: Function,
+}
+
+impl Expr for Lambda {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        Ok(LoxValue::Function(
+            self.function.callable(&env, String::from("<lambda>")),
+        ))
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Stmt(&self.function)]
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Lambda
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!("(lambda {})", self.function.to_sexpr())
+    }
+}
+
+pub struct Import {
+    pub(crate) path: Token,
+}
+
+impl Expr for Import {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        module::import_module(&self.path, &env)
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Import
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!("(import {})", write_token(&self.path))
+    }
+}
+
+// Expressions that nest others count towards the maximum evaluation depth.
+fn enter(env: &Environment, token: &Token) -> Result<Depth, (String, Token)> {
+    Depth::enter(&env.runtime).map_err(|msg| (msg, token.clone()))
 }
 
 pub fn is_truthy(val: LoxValue, invert: bool) -> Result<LoxValue, (String, Token)> {
     match val {
         LoxValue::Bool(a) => {
             if invert {
-                Ok(LoxValue::Bool(!a.clone()))
+                Ok(LoxValue::Bool(!a))
             } else {
                 Ok(val.clone())
             }
@@ -400,6 +824,40 @@ pub fn is_truthy(val: LoxValue, invert: bool) -> Result<LoxValue, (String, Token
     }
 }
 
+// Integers stay exact while the result fits, otherwise the operation is done on floats.
+fn arithmetic(
+    left: LoxValue,
+    right: LoxValue,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Option<LoxValue> {
+    match (left, right) {
+        (LoxValue::Int(a), LoxValue::Int(b)) => Some(match int_op(a, b) {
+            Some(result) => LoxValue::Int(result),
+            None => LoxValue::Number(float_op(a as f64, b as f64)),
+        }),
+        (left, right) => Some(LoxValue::Number(float_op(
+            left.as_number()?,
+            right.as_number()?,
+        ))),
+    }
+}
+
+fn compare(
+    left: LoxValue,
+    right: LoxValue,
+    int_op: fn(&i64, &i64) -> bool,
+    float_op: fn(&f64, &f64) -> bool,
+) -> Option<LoxValue> {
+    match (left, right) {
+        (LoxValue::Int(a), LoxValue::Int(b)) => Some(LoxValue::Bool(int_op(&a, &b))),
+        (left, right) => Some(LoxValue::Bool(float_op(
+            &left.as_number()?,
+            &right.as_number()?,
+        ))),
+    }
+}
+
 fn is_equal(val1: LoxValue, val2: LoxValue, invert: bool) -> LoxValue {
     if invert {
         LoxValue::Bool(val1 != val2)
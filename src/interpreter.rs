@@ -1,60 +1,270 @@
 use crate::environment::Environment;
-use crate::loxvalue::{Callable, LoxValue};
-use crate::stmt::Stmt;
+use crate::loxvalue::{LoxValue, NumberFormat};
+use crate::natives::define_natives;
+use crate::runtime::{Input, Trace};
+use crate::stmt::{self, Stmt, StmtKind};
 use crate::token::Token;
-use crate::tokentype::TokenType;
-use std::cell::RefCell;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Once;
+
+static QUIET_HOOK: Once = Once::new();
+
+thread_local! {
+    // Whether this thread is inside `interpret` with quiet panics, the panic hook stays quiet then.
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
 
 pub struct Interpreter {
     environment: Rc<Environment>,
+    // Strict mode requires every variable to be declared before use, checked while parsing.
+    pub(crate) strict: bool,
+    // In the REPL the value of the last expression statement is kept in `_`.
+    pub(crate) repl: bool,
+    // Of the top level statement running, a panic inside it is reported on this line.
+    line: u64,
+    // From `with_globals`, a reset defines them again.
+    host_globals: HashMap<String, LoxValue>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         let env = Rc::new(Environment::new());
-        let callable = Callable {
-            arity: 0,
-            function: Rc::new(|_arguments, _env| {
-                Ok(LoxValue::Number(
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("time went backwards")
-                        .as_secs_f64(),
-                ))
-            }),
-            string: "<native fn>".to_string(),
-            name: Token {
-                token_type: TokenType::Identifier,
-                lexeme: "clock".to_string(),
-                literal: LoxValue::None,
-                line: 0,
-            },
-            environment: Rc::clone(&env),
-            is_initializer: RefCell::new(false),
-        };
-        env.define(String::from("clock"), LoxValue::Function(Rc::new(callable)));
-        Interpreter { environment: env }
+        env.runtime.globals.replace(Rc::downgrade(&env));
+        define_natives(&env);
+        Interpreter {
+            environment: env,
+            strict: false,
+            repl: false,
+            line: 0,
+            host_globals: HashMap::new(),
+        }
+    }
+
+    // Globals from the host on top of the natives, scripts can read and reassign them like any
+    // other global.
+    pub fn with_globals(globals: HashMap<String, LoxValue>) -> Self {
+        let mut interpreter = Interpreter::new();
+        for (name, value) in &globals {
+            interpreter.environment.define(name.clone(), value.clone());
+        }
+        interpreter.host_globals = globals;
+        interpreter
     }
 
     pub fn new_with_env(environment: Rc<Environment>) -> Self {
         Interpreter {
             environment: Rc::clone(&environment),
+            strict: false,
+            repl: false,
+            line: 0,
+            host_globals: HashMap::new(),
+        }
+    }
+
+    // Drops everything defined by earlier runs, leaving only the natives and the globals given to
+    // `with_globals`, with the values they had then.
+    pub fn reset(&mut self) {
+        let runtime = Rc::new(self.environment.runtime.reset());
+        self.environment = Rc::new(Environment::new_with_runtime(Rc::clone(&runtime)));
+        runtime.globals.replace(Rc::downgrade(&self.environment));
+        define_natives(&self.environment);
+        for (name, value) in &self.host_globals {
+            self.environment.define(name.clone(), value.clone());
+        }
+    }
+
+    // At most `limit` instances can be alive at the same time, None for no limit.
+    pub fn set_allocation_limit(&mut self, limit: Option<usize>) {
+        self.environment.runtime.allocation_limit.set(limit);
+    }
+
+    // At most `limit` bytes can be written by print and the output natives, None for no limit.
+    pub fn set_output_limit(&mut self, limit: Option<usize>) {
+        self.environment.runtime.output_limit.set(limit);
+    }
+
+    // Readable tree of every variable in scope, for poking around in the REPL.
+    pub fn dump_state(&self) -> String {
+        self.environment.dump()
+    }
+
+    // Imports in the script resolve against its directory, and importing the script itself is a no-op.
+    pub(crate) fn set_script(&mut self, path: &Path) {
+        let runtime = &self.environment.runtime;
+        runtime.script.replace(Some(path.to_path_buf()));
+        if let Some(dir) = path.parent() {
+            runtime.base_dir.replace(dir.to_path_buf());
         }
+        if let Ok(canonical) = path.canonicalize() {
+            runtime.imported.borrow_mut().insert(canonical);
+        }
+    }
+
+    pub fn script_path(&self) -> Option<PathBuf> {
+        self.environment.runtime.script.borrow().clone()
+    }
+
+    // Deep recursion or huge expressions error at this depth instead of overflowing the stack.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.environment.runtime.max_depth.set(max_depth);
+    }
+
+    pub fn set_strict_math(&mut self, strict_math: bool) {
+        self.environment.runtime.strict_math.set(strict_math);
+    }
+
+    pub fn set_allow_sleep(&mut self, allow_sleep: bool) {
+        self.environment.runtime.allow_sleep.set(allow_sleep);
+    }
+
+    pub fn set_allow_io(&mut self, allow_io: bool) {
+        self.environment.runtime.allow_io.set(allow_io);
+    }
+
+    pub fn set_allow_eval(&mut self, allow_eval: bool) {
+        self.environment.runtime.allow_eval.set(allow_eval);
     }
 
+    pub fn set_allow_breakpoint(&mut self, allow_breakpoint: bool) {
+        self.environment
+            .runtime
+            .allow_breakpoint
+            .set(allow_breakpoint);
+    }
+
+    // Where breakpoint reads from once allowed, None goes back to stdin.
+    pub fn set_breakpoint_input(&mut self, input: Option<Input>) {
+        self.environment.runtime.breakpoint_input.replace(input);
+    }
+
+    // How print, write and printf show floats.
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.environment.runtime.number_format.set(format);
+    }
+
+    // Counts calls and the time spent in them per function, for the `profile_report` native.
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.environment
+            .runtime
+            .profile
+            .replace(profiling.then(HashMap::new));
+    }
+
+    // Output of print and the output natives is kept for `take_output` instead of printed.
+    pub fn capture_output(&mut self) {
+        self.environment.runtime.output.replace(Some(String::new()));
+    }
+
+    // What was captured, printing goes back to stdout afterwards.
+    pub fn take_output(&mut self) -> String {
+        self.environment
+            .runtime
+            .output
+            .borrow_mut()
+            .take()
+            .unwrap_or_default()
+    }
+
+    // `_`, set by expression statements in REPL mode.
+    pub(crate) fn last_value(&self) -> Option<LoxValue> {
+        self.environment.values.borrow().get("_").cloned()
+    }
+
+    // Panics turned into errors are still reported by the panic hook unless this is set. The first
+    // interpret with it set wraps the process-wide hook in one that skips them.
+    pub fn set_quiet_panics(&mut self, quiet_panics: bool) {
+        self.environment.runtime.quiet_panics.set(quiet_panics);
+    }
+
+    // Replaces the trace hook, None turns tracing off.
+    pub fn set_trace(&mut self, trace: Option<Trace>) {
+        self.environment.runtime.trace.replace(trace);
+    }
+
+    pub(crate) fn native_names(&self) -> HashSet<String> {
+        self.environment.runtime.natives.borrow().clone()
+    }
+
+    pub(crate) fn global_names(&self) -> Vec<String> {
+        self.environment.values.borrow().keys().cloned().collect()
+    }
+
+    // A panic inside becomes an error on the line of its top level statement. A stack overflow
+    // still aborts, Rust can't unwind that.
     pub fn interpret(
         &mut self,
         statements: Vec<Rc<dyn Stmt>>,
     ) -> Result<LoxValue, (String, Token)> {
+        let quiet = self.environment.runtime.quiet_panics.get();
+        if quiet {
+            QUIET_HOOK.call_once(|| {
+                let hook = panic::take_hook();
+                panic::set_hook(Box::new(move |info| {
+                    if !QUIET.with(Cell::get) {
+                        hook(info);
+                    }
+                }));
+            });
+        }
+        let outer = QUIET.with(|flag| flag.replace(quiet));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.execute(statements)));
+        QUIET.with(|flag| flag.set(outer));
+        match result {
+            Ok(result) => result,
+            Err(payload) => {
+                let reason = match payload.downcast_ref::<&str>() {
+                    Some(reason) => String::from(*reason),
+                    None => match payload.downcast_ref::<String>() {
+                        Some(reason) => reason.clone(),
+                        None => String::from("unknown"),
+                    },
+                };
+                let mut token = Token::synthetic("interpret");
+                token.line = self.line;
+                Err((format!("Internal error: {}.", reason), token))
+            }
+        }
+    }
+
+    // Batch mode, an error ends only its own top level statement and the rest still runs.
+    pub fn interpret_all(&mut self, statements: Vec<Rc<dyn Stmt>>) -> Vec<(String, Token)> {
+        let mut errors = Vec::new();
+        stmt::hoist(&statements, &self.environment);
+        for statement in statements {
+            if let Err(error) = self.interpret(vec![statement]) {
+                errors.push(error);
+            }
+        }
+        errors
+    }
+
+    // Function bodies and modules run in here, already inside the catch_unwind of `interpret`.
+    pub(crate) fn execute(
+        &mut self,
+        statements: Vec<Rc<dyn Stmt>>,
+    ) -> Result<LoxValue, (String, Token)> {
+        stmt::hoist(&statements, &self.environment);
         for statement in statements {
-            match statement.evaluate(Rc::clone(&self.environment)) {
+            self.line = statement.line();
+            let expression = self.repl && matches!(statement.kind(), StmtKind::Expression);
+            match stmt::execute(&statement, Rc::clone(&self.environment)) {
                 Ok(LoxValue::Return(value)) => {
                     return Ok(*value);
                 }
+                Ok(value) if expression => self.environment.define(String::from("_"), value),
                 Ok(_) => {}
-                Err((msg, token)) => return Err((String::from(msg), token.clone())),
+                Err((msg, token)) => return Err((msg, token.clone())),
             }
         }
         Ok(LoxValue::None)
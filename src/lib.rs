@@ -0,0 +1,17 @@
+pub mod diagnostic;
+pub mod environment;
+pub mod expr;
+pub mod interpreter;
+pub mod lint;
+pub mod lox;
+pub mod loxvalue;
+pub mod module;
+pub mod natives;
+pub mod parser;
+pub mod runtime;
+pub mod scanner;
+pub mod sexpr;
+pub mod stmt;
+pub mod token;
+pub mod tokentype;
+pub mod visitor;
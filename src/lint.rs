@@ -0,0 +1,90 @@
+use crate::expr::{Expr, Kind};
+use crate::loxvalue::LoxValue;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use crate::tokentype::TokenType;
+use crate::visitor::{walk_expr, Node, Visitor};
+use std::rc::Rc;
+
+// Expressions that are certain to fail at runtime, like `1 + "a"`. Only literal operands are
+// checked, anything involving a variable or a call could be fine so it is left alone.
+pub fn check(statements: &[Rc<dyn Stmt>]) -> Vec<(Token, String)> {
+    let mut lint = Lint {
+        warnings: Vec::new(),
+    };
+    for statement in statements {
+        statement.accept(&mut lint);
+    }
+    lint.warnings
+}
+
+struct Lint {
+    warnings: Vec<(Token, String)>,
+}
+
+impl Visitor for Lint {
+    fn visit_expr(&mut self, expr: &dyn Expr) {
+        let children = expr.children();
+        let operand = |index: usize| match children.get(index) {
+            Some(Node::Expr(child)) => literal(*child),
+            _ => None,
+        };
+        let warning = match expr.kind() {
+            Kind::Binary(operator) => match (operand(0), operand(1)) {
+                (Some(left), Some(right)) => {
+                    binary_mismatch(&operator, &left, &right).map(|msg| (operator, msg))
+                }
+                _ => None,
+            },
+            Kind::Call(paren) => {
+                operand(0).map(|_| (paren, "Can only call functions and classes."))
+            }
+            Kind::Get(name, _) => operand(0).map(|_| (name, "Only instances have properties.")),
+            _ => None,
+        };
+        if let Some((token, msg)) = warning {
+            self.warnings.push((token, String::from(msg)));
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// The value of a literal, also when it is in parentheses.
+fn literal(expr: &dyn Expr) -> Option<LoxValue> {
+    match expr.kind() {
+        Kind::Literal(value) => Some(value),
+        Kind::Grouping => match expr.children().first() {
+            Some(Node::Expr(inner)) => literal(*inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The same operand rules as `Binary::evaluate`, with its messages.
+fn binary_mismatch(operator: &Token, left: &LoxValue, right: &LoxValue) -> Option<&'static str> {
+    let numbers = left.as_number().is_some() && right.as_number().is_some();
+    match operator.token_type {
+        TokenType::Plus => match (left, right) {
+            (LoxValue::String(_), LoxValue::String(_)) => None,
+            _ if numbers => None,
+            _ => Some("Can only add two numbers or concatenate two strings."),
+        },
+        TokenType::Minus if !numbers => Some("Can only subtract two numbers."),
+        TokenType::Slash if !numbers => Some("Can only divide two numbers."),
+        TokenType::Star => match (left, right) {
+            (LoxValue::String(_), count) if count.as_number().is_some() => match count.as_index() {
+                Some(_) => None,
+                None => Some("Can only repeat a string a non-negative whole number of times."),
+            },
+            _ if numbers => None,
+            _ => Some("Can only multiply two numbers."),
+        },
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+            if !numbers =>
+        {
+            Some("Can only compare two numbers.")
+        }
+        _ => None,
+    }
+}
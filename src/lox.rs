@@ -1,27 +1,156 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use crate::interpreter::Interpreter;
-use crate::parser::Parser;
+use crate::lint;
+use crate::loxvalue::{LoxValue, NumberFormat};
+use crate::parser::{self, Parser};
+use crate::runtime::Trace;
 use crate::scanner::Scanner;
+use crate::sexpr;
+use crate::stmt::Stmt;
 use crate::token::Token;
 use crate::tokentype::TokenType;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::{fs, io};
 
 pub struct Lox {
     had_error: bool,
     had_runtime_error: bool,
     interpreter: Interpreter,
+    lint: bool,
+    max_nesting: usize,
+    asi: bool,
+    continue_on_error: bool,
+    // Of the current run, handed out at its end.
+    diagnostics: Vec<Diagnostic>,
+    // Whether diagnostics also go to stderr, the CLI turns it on.
+    print_diagnostics: bool,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
 }
 
 impl Lox {
     pub fn new() -> Self {
+        Lox::with_interpreter(Interpreter::new())
+    }
+
+    pub fn with_globals(globals: HashMap<String, LoxValue>) -> Self {
+        Lox::with_interpreter(Interpreter::with_globals(globals))
+    }
+
+    fn with_interpreter(interpreter: Interpreter) -> Self {
         Lox {
             had_error: false,
             had_runtime_error: false,
-            interpreter: Interpreter::new(),
+            interpreter,
+            lint: false,
+            max_nesting: parser::DEFAULT_MAX_NESTING,
+            asi: false,
+            continue_on_error: false,
+            diagnostics: Vec::new(),
+            print_diagnostics: false,
         }
     }
 
+    // Off by default, hosts get the diagnostics back from `run` to show them their own way.
+    pub fn set_print_diagnostics(&mut self, print_diagnostics: bool) {
+        self.print_diagnostics = print_diagnostics;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.interpreter.strict = strict;
+    }
+
+    // Warns about expressions that are sure to fail before running them.
+    pub fn set_lint(&mut self, lint: bool) {
+        self.lint = lint;
+    }
+
+    // Reports every runtime error and a count at the end instead of stopping at the first one.
+    pub fn set_continue_on_error(&mut self, continue_on_error: bool) {
+        self.continue_on_error = continue_on_error;
+    }
+
+    pub fn set_allocation_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.set_allocation_limit(limit);
+    }
+
+    pub fn set_output_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.set_output_limit(limit);
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.interpreter.set_max_depth(max_depth);
+    }
+
+    // How deep parentheses and assignments can nest before parsing stops with an error.
+    pub fn set_max_nesting(&mut self, max_nesting: usize) {
+        self.max_nesting = max_nesting;
+    }
+
+    // Simple statements can end at a line break instead of a ';'.
+    pub fn set_asi(&mut self, asi: bool) {
+        self.asi = asi;
+    }
+
+    pub fn set_strict_math(&mut self, strict_math: bool) {
+        self.interpreter.set_strict_math(strict_math);
+    }
+
+    pub fn set_allow_sleep(&mut self, allow_sleep: bool) {
+        self.interpreter.set_allow_sleep(allow_sleep);
+    }
+
+    pub fn set_allow_io(&mut self, allow_io: bool) {
+        self.interpreter.set_allow_io(allow_io);
+    }
+
+    pub fn set_allow_eval(&mut self, allow_eval: bool) {
+        self.interpreter.set_allow_eval(allow_eval);
+    }
+
+    // breakpoint() stops for input on stdin when allowed, otherwise it is a no-op.
+    pub fn set_allow_breakpoint(&mut self, allow_breakpoint: bool) {
+        self.interpreter.set_allow_breakpoint(allow_breakpoint);
+    }
+
+    pub fn set_quiet_panics(&mut self, quiet_panics: bool) {
+        self.interpreter.set_quiet_panics(quiet_panics);
+    }
+
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.interpreter.set_number_format(format);
+    }
+
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.interpreter.set_profiling(profiling);
+    }
+
+    pub fn set_trace(&mut self, trace: Option<Trace>) {
+        self.interpreter.set_trace(trace);
+    }
+
+    // The file given to `run_file`, None when only the REPL ran.
+    pub fn script_path(&self) -> Option<PathBuf> {
+        self.interpreter.script_path()
+    }
+
+    // Forgets earlier runs, the next run starts from just the natives and the globals given to
+    // `with_globals` again.
+    pub fn reset(&mut self) {
+        self.had_error = false;
+        self.had_runtime_error = false;
+        self.interpreter.reset();
+    }
+
     pub fn run_file(&mut self, path: &String) {
+        self.interpreter.set_script(Path::new(path));
         self.run(fs::read_to_string(path).unwrap(), true);
         if self.had_error {
             std::process::exit(65);
@@ -32,8 +161,33 @@ impl Lox {
         }
     }
 
+    // Prints the parsed program in the form `run_ast_file` runs, instead of running it.
+    pub fn dump_ast_file(&mut self, path: &str) {
+        let source = fs::read_to_string(path).unwrap();
+        match self.parse_source(source, true) {
+            Some(statements) => print!("{}", sexpr::to_sexpr(&statements)),
+            None => std::process::exit(65),
+        }
+    }
+
+    // Runs a program printed by `dump_ast_file`, without scanning or parsing it again.
+    pub fn run_ast_file(&mut self, path: &str) {
+        self.interpreter.set_script(Path::new(path));
+        match sexpr::from_sexpr(&fs::read_to_string(path).unwrap()) {
+            Ok(statements) => self.interpret(statements),
+            Err(msg) => {
+                self.error(0, msg);
+                std::process::exit(65);
+            }
+        }
+        if self.had_runtime_error {
+            std::process::exit(70);
+        }
+    }
+
     pub fn run_prompt(&mut self) {
         let stdin = io::stdin();
+        self.interpreter.repl = true;
 
         loop {
             print!("> ");
@@ -51,7 +205,21 @@ impl Lox {
         }
     }
 
-    fn run(&mut self, source: String, quit_on_error: bool) {
+    // Runs the source, returning what was reported on the way. Printed as well when
+    // `set_print_diagnostics` is on.
+    pub fn run(&mut self, source: String, quit_on_error: bool) -> Vec<Diagnostic> {
+        self.run_source(source, quit_on_error);
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn run_source(&mut self, source: String, quit_on_error: bool) {
+        if let Some(statements) = self.parse_source(source, quit_on_error) {
+            self.interpret(statements);
+        }
+    }
+
+    // None when there were errors and the run should stop.
+    fn parse_source(&mut self, source: String, quit_on_error: bool) -> Option<Vec<Rc<dyn Stmt>>> {
         let mut scanner = Scanner::new(source);
         let tokens: Vec<Token> = match scanner.scan_tokens() {
             Ok(a) => a,
@@ -61,46 +229,130 @@ impl Lox {
             }
         };
         if quit_on_error && (self.had_error || self.had_runtime_error) {
-            return;
+            return None;
         }
-        let mut parser = Parser::new(tokens);
+        let mut parser = if self.interpreter.strict {
+            Parser::new_strict(tokens, self.interpreter.global_names())
+        } else {
+            Parser::new(tokens)
+        };
+        parser.set_max_nesting(self.max_nesting);
+        parser.set_asi(self.asi);
+        parser.set_natives(self.interpreter.native_names());
         let (statements, errors) = parser.parse();
         for (token, msg) in errors {
-            self.error_parse(&token, &*msg);
+            self.error_parse(&token, &msg);
+        }
+        if self.lint {
+            for (token, msg) in parser.take_warnings() {
+                self.report(DiagnosticKind::Warning, Some(&token), token.line, msg);
+            }
         }
         if quit_on_error && (self.had_error || self.had_runtime_error) {
+            return None;
+        }
+        if self.lint {
+            for (token, msg) in lint::check(&statements) {
+                self.report(DiagnosticKind::Warning, Some(&token), token.line, msg);
+            }
+        }
+        Some(statements)
+    }
+
+    fn interpret(&mut self, statements: Vec<Rc<dyn Stmt>>) {
+        if self.continue_on_error {
+            let errors = self.interpreter.interpret_all(statements);
+            let count = errors.len();
+            for error in errors {
+                self.runtime_error(error);
+            }
+            if count > 0 && self.print_diagnostics {
+                eprintln!("{} runtime error(s).", count);
+            }
             return;
         }
         match self.interpreter.interpret(statements) {
             Ok(_) => {}
-            Err((msg, token)) => self.runtime_error((String::from(msg), token.clone())),
+            Err((msg, token)) => self.runtime_error((msg, token.clone())),
         }
     }
 
     pub fn error(&mut self, line: u64, message: String) {
-        self.report(line, String::from(""), message);
+        self.had_error = true;
+        self.report(DiagnosticKind::Error, None, line, message);
     }
 
-    fn report(&mut self, line: u64, where_error: String, message: String) {
-        eprintln!("[line {}] Error {}: {}", line, where_error, message);
-        self.had_error = true;
+    fn report(&mut self, kind: DiagnosticKind, token: Option<&Token>, line: u64, message: String) {
+        let diagnostic = Diagnostic {
+            kind,
+            message,
+            line,
+            column: token
+                .and_then(|token| token.column)
+                .map(|column| column + 1),
+            location: token.map_or_else(String::new, |token| match token.token_type {
+                TokenType::EOF => String::from("at end"),
+                _ => format!("at '{}'", token.lexeme),
+            }),
+            context: token
+                .filter(|_| kind == DiagnosticKind::RuntimeError)
+                .and_then(source_context),
+        };
+        if self.print_diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
     }
 
     pub fn error_parse(&mut self, token: &Token, msg: &str) {
         self.had_error = true;
-        match token.token_type {
-            TokenType::EOF => self.report(token.line, String::from("at end"), String::from(msg)),
-            _ => self.report(
-                token.line,
-                format!("at '{}'", token.lexeme),
-                String::from(msg),
-            ),
-        }
+        self.report(
+            DiagnosticKind::Error,
+            Some(token),
+            token.line,
+            String::from(msg),
+        );
     }
 
     pub fn runtime_error(&mut self, error: (String, Token)) {
         let (msg, token) = error;
-        eprintln!("{}\n[line {}]", msg, token.line);
+        self.report(DiagnosticKind::RuntimeError, Some(&token), token.line, msg);
         self.had_runtime_error = true;
     }
 }
+
+// The source line of the token, with its lexeme underlined when its column is known. Taken from
+// the text the token was scanned from, which is another file for a token of an imported module.
+fn source_context(token: &Token) -> Option<String> {
+    let source = token.source.as_ref()?;
+    // The same line ends as the scanner, \r\n, \n or a lone \r.
+    let line = source
+        .replace("\r\n", "\n")
+        .split(['\n', '\r'])
+        .nth((token.line as usize).checked_sub(1)?)?
+        .to_string();
+    let mut context = format!("    {}", line);
+    if let Some(column) = token.column {
+        context.push_str(&format!(
+            "\n    {}{}",
+            " ".repeat(column),
+            "^".repeat(token.lexeme.chars().count())
+        ));
+    }
+    Some(context)
+}
+
+// Runs the source on a fresh interpreter without printing anything, for tests. Gives the value
+// of the last expression statement, everything printed and the diagnostics.
+pub fn run_capturing(source: &str) -> (Option<LoxValue>, String, Vec<Diagnostic>) {
+    let mut lox = Lox::new();
+    lox.interpreter.repl = true;
+    lox.interpreter.capture_output();
+    let diagnostics = lox.run(String::from(source), true);
+    let value = lox.interpreter.last_value();
+    let output = lox.interpreter.take_output();
+    if lox.had_error || lox.had_runtime_error {
+        return (None, output, diagnostics);
+    }
+    (value, output, diagnostics)
+}
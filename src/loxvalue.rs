@@ -1,37 +1,54 @@
 use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::runtime::{Allocation, Runtime};
 use crate::token::Token;
 use std::borrow::Borrow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub enum LoxValue {
     String(String),
+    // Binary data, kept apart from strings so it doesn't have to be valid UTF-8.
+    Bytes(Rc<RefCell<Vec<u8>>>),
     Number(f64),
+    Int(i64),
     Bool(bool),
     None,
     Function(Rc<Callable>),
     Return(Box<LoxValue>),
     Class(Rc<Class>),
     Instance(Rc<InstanceValue>),
+    Module(Rc<Module>),
+    // A `lazy const` not read yet. Reading the variable forces it, expressions never see one.
+    Lazy(Rc<Lazy>),
 }
 
 #[derive(Debug, Clone)]
 pub struct InstanceValue {
     pub(crate) class: Rc<Class>,
     pub(crate) fields: RefCell<HashMap<String, LoxValue>>,
+    // Only held so the runtime can count live instances.
+    pub(crate) _allocation: Rc<Allocation>,
 }
 
 impl InstanceValue {
-    pub fn get_value(&self, name: &Token) -> Result<LoxValue, (String, Token)> {
+    pub fn get_value(self: &Rc<Self>, name: &Token) -> Result<LoxValue, (String, Token)> {
+        if let Some(getter) = self.class.find_getter(&name.lexeme) {
+            getter.bind(LoxValue::Instance(Rc::clone(self)));
+            return getter.call(Vec::new());
+        }
+
         match self.class.find_method(name.clone().lexeme) {
             None => {}
             Some(callable) => {
                 let updated_method = callable.clone();
-                updated_method.bind(LoxValue::Instance(Rc::new(self.clone())));
+                updated_method.bind(LoxValue::Instance(Rc::clone(self)));
                 return Ok(LoxValue::Function(updated_method));
             }
         }
@@ -48,6 +65,99 @@ impl InstanceValue {
     pub fn set_value(&self, name: String, value: LoxValue) {
         self.fields.borrow_mut().insert(name, value);
     }
+
+    // Assignment from a script, which goes through a setter when the class has one. A getter
+    // without a setter makes the property read-only, a field of that name would never be read.
+    pub fn set_property(
+        self: &Rc<Self>,
+        name: &Token,
+        value: LoxValue,
+    ) -> Result<(), (String, Token)> {
+        match self.class.find_setter(&name.lexeme) {
+            Some(setter) => {
+                setter.bind(LoxValue::Instance(Rc::clone(self)));
+                setter.call(vec![value])?;
+            }
+            None if self.class.find_getter(&name.lexeme).is_some() => {
+                return Err((
+                    String::from("Can't set a read-only property."),
+                    name.clone(),
+                ));
+            }
+            None => self.set_value(name.lexeme.clone(), value),
+        }
+        Ok(())
+    }
+}
+
+// The top level of an imported file, its variables and functions are the properties.
+pub struct Module {
+    pub(crate) name: String,
+    pub(crate) environment: Rc<Environment>,
+}
+
+impl Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module").field("name", &self.name).finish()
+    }
+}
+
+impl Module {
+    pub fn get_value(&self, name: &Token) -> Result<LoxValue, (String, Token)> {
+        // Cloned out first, a lazy initializer can define variables in the module.
+        let value = self.environment.values.borrow().get(&*name.lexeme).cloned();
+        match value {
+            None => Err((
+                format!(
+                    "Undefined property '{}' in module '{}'.",
+                    name.lexeme, self.name
+                ),
+                name.clone(),
+            )),
+            Some(LoxValue::Lazy(lazy)) => lazy.force(),
+            Some(value) => Ok(value),
+        }
+    }
+}
+
+// A `lazy const`, the initializer runs on the first read and its value is kept for the next ones.
+pub struct Lazy {
+    pub(crate) name: Token,
+    pub(crate) initializer: Rc<dyn Expr>,
+    // Where it was declared, the initializer sees the variables from there.
+    pub(crate) environment: Rc<Environment>,
+    pub(crate) value: RefCell<Option<LoxValue>>,
+    // While the initializer runs, reading the constant from it is an error instead of a loop.
+    pub(crate) running: Cell<bool>,
+}
+
+impl Debug for Lazy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy").field("name", &self.name).finish()
+    }
+}
+
+impl Lazy {
+    // An initializer that errors isn't kept, the next read runs it again.
+    pub(crate) fn force(&self) -> Result<LoxValue, (String, Token)> {
+        if let Some(value) = self.value.borrow().as_ref() {
+            return Ok(value.clone());
+        }
+        if self.running.replace(true) {
+            return Err((
+                format!(
+                    "Lazy constant '{}' is read by its own initializer.",
+                    self.name.lexeme
+                ),
+                self.name.clone(),
+            ));
+        }
+        let value = self.initializer.evaluate(Rc::clone(&self.environment));
+        self.running.set(false);
+        let value = value?;
+        self.value.replace(Some(value.clone()));
+        Ok(value)
+    }
 }
 
 #[derive(Debug)]
@@ -55,7 +165,15 @@ pub struct Class {
     pub(crate) name: String,
     pub(crate) arity: usize,
     pub(crate) methods: RefCell<HashMap<String, LoxValue>>,
+    // `get name { ... }` and `set name(value) { ... }`, run on reading and assigning the property.
+    pub(crate) getters: HashMap<String, Rc<Callable>>,
+    pub(crate) setters: HashMap<String, Rc<Callable>>,
+    // Fields of the class itself, `Class.name`, usually set up in a `static { ... }` block.
+    pub(crate) statics: RefCell<HashMap<String, LoxValue>>,
+    // `static name() { ... }`, `this` is bound to the class they are called on.
+    pub(crate) static_methods: HashMap<String, Rc<Callable>>,
     pub(crate) super_class: Option<Rc<Class>>,
+    pub(crate) runtime: Rc<Runtime>,
 }
 
 impl Clone for Class {
@@ -64,26 +182,34 @@ impl Clone for Class {
             name: self.name.clone(),
             arity: self.arity,
             methods: RefCell::clone(&self.methods),
+            getters: self.getters.clone(),
+            setters: self.setters.clone(),
+            statics: RefCell::clone(&self.statics),
+            static_methods: self.static_methods.clone(),
             super_class: self.super_class.clone(),
+            runtime: Rc::clone(&self.runtime),
         }
     }
 }
 
 impl Class {
-    pub(crate) fn call(&self, arguments: Vec<LoxValue>) -> Result<LoxValue, (String, Token)> {
+    // On the `Rc`, so the instance's class is this very class and `class_of` gives it back.
+    pub(crate) fn call(
+        self: &Rc<Self>,
+        arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, (String, Token)> {
+        let allocation = match self.runtime.allocate() {
+            Ok(allocation) => allocation,
+            Err(msg) => return Err((msg, Token::synthetic(&self.name))),
+        };
         let instance = Rc::new(InstanceValue {
-            class: Rc::new(self.clone()),
+            class: Rc::clone(self),
             fields: RefCell::new(HashMap::new()),
+            _allocation: allocation,
         });
-        match self.methods.borrow().get("init") {
-            Some(a) => match a {
-                LoxValue::Function(callable) => {
-                    callable.bind(LoxValue::Instance(Rc::clone(&instance)));
-                    return callable.call(arguments);
-                }
-                _ => {}
-            },
-            _ => {}
+        if let Some(LoxValue::Function(callable)) = self.methods.borrow().get("init") {
+            callable.bind(LoxValue::Instance(Rc::clone(&instance)));
+            return callable.call(arguments);
         }
         Ok(LoxValue::Instance(instance))
     }
@@ -100,12 +226,56 @@ impl Class {
             },
         }
     }
+
+    // Static fields and methods are inherited, a subclass sees those of its superclass it doesn't
+    // set itself. A static method called on a subclass gets the subclass as `this`.
+    pub(crate) fn get_static(self: &Rc<Self>, name: &Token) -> Result<LoxValue, (String, Token)> {
+        self.find_static(name, self)
+    }
+
+    fn find_static(&self, name: &Token, receiver: &Rc<Class>) -> Result<LoxValue, (String, Token)> {
+        if let Some(value) = self.statics.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = self.static_methods.get(&name.lexeme) {
+            // A copy, binding `this` to the receiver mustn't change it for other receivers.
+            let method = Callable::clone(method);
+            method.bind(LoxValue::Class(Rc::clone(receiver)));
+            return Ok(LoxValue::Function(Rc::new(method)));
+        }
+        match &self.super_class {
+            Some(super_class) => super_class.find_static(name, receiver),
+            None => Err((
+                format!("Undefined property '{}'.", name.lexeme),
+                name.clone(),
+            )),
+        }
+    }
+
+    // Copies, like static methods, so binding `this` for one access doesn't change it for another
+    // still running, such as a getter reading itself on a different instance.
+    pub(crate) fn find_getter(&self, name: &str) -> Option<Rc<Callable>> {
+        match self.getters.get(name) {
+            Some(getter) => Some(Rc::new(Callable::clone(getter))),
+            None => self.super_class.as_ref()?.find_getter(name),
+        }
+    }
+
+    pub(crate) fn find_setter(&self, name: &str) -> Option<Rc<Callable>> {
+        match self.setters.get(name) {
+            Some(setter) => Some(Rc::new(Callable::clone(setter))),
+            None => self.super_class.as_ref()?.find_setter(name),
+        }
+    }
 }
 
+pub type CallableFn = dyn Fn(Vec<LoxValue>, Rc<Environment>) -> Result<LoxValue, (String, Token)>;
+
 pub struct Callable {
     pub(crate) arity: usize,
-    pub(crate) function:
-        Rc<dyn Fn(Vec<LoxValue>, Rc<Environment>) -> Result<LoxValue, (String, Token)>>,
+    // Variadic callables take `arity` or more arguments.
+    pub(crate) variadic: bool,
+    pub(crate) function: Rc<CallableFn>,
     pub(crate) string: String,
     pub(crate) name: Token,
     // Below environment is the closure
@@ -129,6 +299,7 @@ impl Clone for Callable {
         let env_clone = Rc::new(borrow.clone());
         Callable {
             arity: self.arity,
+            variadic: self.variadic,
             function: Rc::clone(&self.function),
             string: self.string.clone(),
             name: self.name.clone(),
@@ -140,15 +311,8 @@ impl Clone for Callable {
 
 impl Callable {
     pub(crate) fn call(&self, arguments: Vec<LoxValue>) -> Result<LoxValue, (String, Token)> {
-        if self.arity != arguments.len() {
-            return Err((
-                format!(
-                    "Expected {} argument(s) but got {}.",
-                    self.arity,
-                    arguments.len()
-                ),
-                self.name.clone(),
-            ));
+        if let Some(msg) = self.arity_error(arguments.len()) {
+            return Err((msg, self.name.clone()));
         };
 
         self.environment.define(
@@ -156,7 +320,14 @@ impl Callable {
             LoxValue::Function(Rc::new(self.clone())),
         );
 
+        let runtime = &self.environment.runtime;
+        let start = runtime.profile.borrow().is_some().then(Instant::now);
         let result = (self.function)(arguments, Rc::clone(&self.environment));
+        if let Some(start) = start {
+            runtime.record_call(&self.name.lexeme, start.elapsed());
+        }
+        // An initializer gives back `this` whatever it returns, but its errors still count.
+        let result = result?;
 
         if *self.is_initializer.borrow() {
             match self.environment.get_by_string(String::from("this")) {
@@ -164,10 +335,36 @@ impl Callable {
                 Err(msg) => Err((msg, self.name.clone())),
             }
         } else {
-            result
+            Ok(result)
         }
     }
 
+    pub(crate) fn arity_error(&self, count: usize) -> Option<String> {
+        if self.variadic && count < self.arity {
+            Some(format!(
+                "Expected at least {} argument(s) but got {}.",
+                self.arity, count
+            ))
+        } else if !self.variadic && count != self.arity {
+            Some(format!(
+                "Expected {} argument(s) but got {}.",
+                self.arity, count
+            ))
+        } else {
+            None
+        }
+    }
+
+    // Whether the closure is a snapshot of `env`, natives use the globals themselves.
+    pub(crate) fn declared_in(&self, env: &Rc<Environment>) -> bool {
+        !Rc::ptr_eq(&self.environment, env)
+            && match (&self.environment.enclosing, &env.enclosing) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+
     pub(crate) fn bind(&self, instance: LoxValue) {
         self.environment.define(String::from("this"), instance);
     }
@@ -181,14 +378,70 @@ impl Callable {
     }
 }
 
+impl LoxValue {
+    pub(crate) fn as_number(&self) -> Option<f64> {
+        match self {
+            LoxValue::Number(a) => Some(*a),
+            LoxValue::Int(a) => Some(*a as f64),
+            _ => None,
+        }
+    }
+
+    // Counts and indexes, 2.0 counts as 2 but a fraction or a negative number is no index.
+    pub(crate) fn as_index(&self) -> Option<usize> {
+        match self {
+            LoxValue::Int(a) => usize::try_from(*a).ok(),
+            LoxValue::Number(a) if a.fract() == 0.0 && *a >= 0.0 => Some(*a as usize),
+            _ => None,
+        }
+    }
+
+    // Identity for the values living behind an Rc, plain equality for the rest.
+    pub(crate) fn is_same(&self, other: &LoxValue) -> bool {
+        match (self, other) {
+            (LoxValue::Function(a), LoxValue::Function(b)) => Rc::ptr_eq(a, b),
+            (LoxValue::Bytes(a), LoxValue::Bytes(b)) => Rc::ptr_eq(a, b),
+            (LoxValue::Class(a), LoxValue::Class(b)) => Rc::ptr_eq(a, b),
+            (LoxValue::Instance(a), LoxValue::Instance(b)) => Rc::ptr_eq(a, b),
+            (LoxValue::Module(a), LoxValue::Module(b)) => Rc::ptr_eq(a, b),
+            _ => self == other,
+        }
+    }
+
+    // Display with the number format of the interpreter, as print shows it.
+    pub(crate) fn display(&self, format: &NumberFormat) -> String {
+        match self {
+            LoxValue::Number(a) => format.format(*a),
+            _ => self.to_string(),
+        }
+    }
+
+    // Like Display but without quotes around strings.
+    pub(crate) fn stringify(&self) -> String {
+        match self {
+            LoxValue::String(a) => a.clone(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+// Numbers compare like IEEE floats, NaN is not equal to anything, itself included, and
+// 0.0 == -0.0. Hashes and cache keys use `number_key` to agree with that, apart from a NaN key
+// finding itself again.
 impl PartialEq for LoxValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (LoxValue::String(a), LoxValue::String(b)) => a == b,
+            (LoxValue::Bytes(a), LoxValue::Bytes(b)) => a == b,
             (LoxValue::Number(a), LoxValue::Number(b)) => a == b,
+            (LoxValue::Int(a), LoxValue::Int(b)) => a == b,
+            (LoxValue::Int(a), LoxValue::Number(b)) | (LoxValue::Number(b), LoxValue::Int(a)) => {
+                int_equals_float(*a, *b)
+            }
             (LoxValue::None, LoxValue::None) => true,
             (LoxValue::Bool(a), LoxValue::Bool(b)) => a == b,
             (LoxValue::Function(a), LoxValue::Function(b)) => Rc::ptr_eq(a, b),
+            (LoxValue::Module(a), LoxValue::Module(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
@@ -196,17 +449,98 @@ impl PartialEq for LoxValue {
 
 impl Eq for LoxValue {}
 
+// The bits of a number used as a key, -0.0 as 0.0 and every NaN as the same NaN.
+pub(crate) fn number_key(value: f64) -> u64 {
+    if value.is_nan() {
+        f64::NAN.to_bits()
+    } else if value == 0.0 {
+        0
+    } else {
+        value.to_bits()
+    }
+}
+
+// Exact, 2^53 + 1 is not equal to the float it rounds to.
+fn int_equals_float(int: i64, float: f64) -> bool {
+    float.fract() == 0.0
+        && float >= i64::MIN as f64
+        && float < -(i64::MIN as f64)
+        && float as i64 == int
+}
+
+// Never depends on the platform or locale. Without `decimals` it is the shortest text that reads
+// back as the same number, with them it rounds half away from zero, 0.125 to 2 places is 0.13.
+pub(crate) fn format_number(value: f64, decimals: Option<usize>) -> String {
+    match decimals {
+        None => value.to_string(),
+        Some(decimals) => {
+            let scale = 10f64.powi(decimals.min(300) as i32);
+            let mut rounded = (value * scale).round() / scale;
+            if !rounded.is_finite() {
+                rounded = value;
+            }
+            // No "-0.00" for small negative numbers.
+            if rounded == 0.0 {
+                rounded = 0.0;
+            }
+            format!("{:.*}", decimals, rounded)
+        }
+    }
+}
+
+// How print and the output natives show floats. The default shows the shortest decimal that
+// reads back as the same number, integers are always shown exactly.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NumberFormat {
+    // Rounds to this many significant digits, at least 1.
+    pub significant_digits: Option<usize>,
+    // Magnitudes from this one up are shown like 1.5e21.
+    pub scientific_above: Option<f64>,
+    // Magnitudes below this one, apart from zero, are shown like 1e-7.
+    pub scientific_below: Option<f64>,
+}
+
+impl NumberFormat {
+    pub(crate) fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return format_number(value, None);
+        }
+        let rounded = match self.significant_digits {
+            Some(digits) => format!("{:.*e}", digits.clamp(1, 17) - 1, value)
+                .parse()
+                .unwrap_or(value),
+            None => value,
+        };
+        let magnitude = rounded.abs();
+        let scientific = self
+            .scientific_above
+            .is_some_and(|above| magnitude >= above)
+            || self
+                .scientific_below
+                .is_some_and(|below| magnitude != 0.0 && magnitude < below);
+        if scientific {
+            format!("{:e}", rounded)
+        } else {
+            format_number(rounded, None)
+        }
+    }
+}
+
 impl fmt::Display for LoxValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             LoxValue::String(a) => write!(f, "\"{}\"", a),
-            LoxValue::Number(a) => write!(f, "{}", a),
+            LoxValue::Bytes(a) => write!(f, "<bytes {}>", RefCell::borrow(a).len()),
+            LoxValue::Number(a) => write!(f, "{}", format_number(*a, None)),
+            LoxValue::Int(a) => write!(f, "{}", a),
             LoxValue::Bool(a) => write!(f, "{}", a),
             LoxValue::None => write!(f, "nil"),
             LoxValue::Function(a) => write!(f, "{}", a.string),
             LoxValue::Return(a) => write!(f, "<return {}>", a),
             LoxValue::Class(a) => write!(f, "{}", a.name),
             LoxValue::Instance(a) => write!(f, "{} instance", a.class.name),
+            LoxValue::Module(a) => write!(f, "<module {}>", a.name),
+            LoxValue::Lazy(a) => write!(f, "<lazy {}>", a.name.lexeme),
         }
     }
 }
@@ -1,24 +1,72 @@
-mod environment;
-mod expr;
-mod interpreter;
-mod lox;
-mod loxvalue;
-mod parser;
-mod scanner;
-mod stmt;
-mod token;
-mod tokentype;
-
-use crate::lox::Lox;
+use rilox::lox::Lox;
 use std::env;
+use std::io::{self, IsTerminal};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
     let mut lox: Lox = Lox::new();
+    lox.set_print_diagnostics(true);
+    lox.set_quiet_panics(true);
+    lox.set_allow_sleep(true);
+    lox.set_allow_io(true);
+    lox.set_allow_breakpoint(io::stdin().is_terminal());
 
-    if args.len() > 2 {
-        println!("Usage: rilox [script] ");
+    if let Some(position) = args.iter().position(|arg| arg == "--strict") {
+        args.remove(position);
+        lox.set_strict(true);
+    }
+
+    if let Some(position) = args.iter().position(|arg| arg == "--keep-going") {
+        args.remove(position);
+        lox.set_continue_on_error(true);
+    }
+
+    if let Some(position) = args.iter().position(|arg| arg == "--lint") {
+        args.remove(position);
+        lox.set_lint(true);
+    }
+
+    if let Some(position) = args.iter().position(|arg| arg == "--asi") {
+        args.remove(position);
+        lox.set_asi(true);
+    }
+
+    // Off by default like for any other host, eval runs whatever text the script builds.
+    if let Some(position) = args.iter().position(|arg| arg == "--allow-eval") {
+        args.remove(position);
+        lox.set_allow_eval(true);
+    }
+
+    if let Some(position) = args.iter().position(|arg| arg == "--profile") {
+        args.remove(position);
+        lox.set_profiling(true);
+    }
+
+    // Print the parsed script instead of running it, or run a script printed like that.
+    let dump_ast = match args.iter().position(|arg| arg == "--dump-ast") {
+        Some(position) => {
+            args.remove(position);
+            true
+        }
+        None => false,
+    };
+    let run_ast = match args.iter().position(|arg| arg == "--ast") {
+        Some(position) => {
+            args.remove(position);
+            true
+        }
+        None => false,
+    };
+
+    if args.len() > 2 || ((dump_ast || run_ast) && args.len() != 2) {
+        println!(
+            "Usage: rilox [--strict] [--lint] [--keep-going] [--asi] [--allow-eval] [--profile] [--dump-ast | --ast] [script] "
+        );
         std::process::exit(64);
+    } else if dump_ast {
+        lox.dump_ast_file(&args[1]);
+    } else if run_ast {
+        lox.run_ast_file(&args[1]);
     } else if args.len() == 2 {
         let source: &String = &args[1];
         lox.run_file(source);
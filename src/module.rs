@@ -0,0 +1,84 @@
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::loxvalue::{LoxValue, Module};
+use crate::natives::define_natives;
+use crate::parser::Parser;
+use crate::runtime::Runtime;
+use crate::scanner::Scanner;
+use crate::token::Token;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Runs the file `path` names in the globals, unless it already ran. `path` is the string token
+// from the import, errors in the imported file are reported there.
+pub(crate) fn import(path: &Token, env: &Rc<Environment>) -> Result<(), (String, Token)> {
+    let resolved = resolve(path, &env.runtime)?;
+    if !env.runtime.imported.borrow_mut().insert(resolved.clone()) {
+        return Ok(());
+    }
+    run(path, resolved, Environment::globals(env))
+}
+
+// Runs the file in its own environment, its top level becomes the properties of the module.
+pub(crate) fn import_module(
+    path: &Token,
+    env: &Rc<Environment>,
+) -> Result<LoxValue, (String, Token)> {
+    let runtime = &env.runtime;
+    let resolved = resolve(path, runtime)?;
+    // Also hit by an import cycle, that module is still running and only partly defined.
+    if let Some(module) = runtime.modules.borrow().get(&resolved) {
+        return Ok(LoxValue::Module(Rc::clone(module)));
+    }
+
+    let natives = Rc::new(Environment::new_with_runtime(Rc::clone(runtime)));
+    define_natives(&natives);
+    let module = Rc::new(Module {
+        name: path.literal.stringify(),
+        environment: Rc::new(Environment::new_child(natives)),
+    });
+    runtime
+        .modules
+        .borrow_mut()
+        .insert(resolved.clone(), Rc::clone(&module));
+    run(path, resolved, Rc::clone(&module.environment))?;
+    Ok(LoxValue::Module(module))
+}
+
+fn resolve(path: &Token, runtime: &Runtime) -> Result<PathBuf, (String, Token)> {
+    let name = path.literal.stringify();
+    match runtime.base_dir.borrow().join(&name).canonicalize() {
+        Ok(resolved) => Ok(resolved),
+        Err(_) => Err((format!("Could not find module '{}'.", name), path.clone())),
+    }
+}
+
+fn run(path: &Token, resolved: PathBuf, env: Rc<Environment>) -> Result<(), (String, Token)> {
+    let name = path.literal.stringify();
+    let error = |msg: String| Err((msg, path.clone()));
+
+    let source = match fs::read_to_string(&resolved) {
+        Ok(source) => source,
+        Err(_) => return error(format!("Could not read module '{}'.", name)),
+    };
+    let tokens = match Scanner::new(source).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err((line, msg)) => return error(format!("{} [line {} in '{}']", msg, line, name)),
+    };
+    let (statements, errors) = Parser::new(tokens).parse();
+    if let Some((token, msg)) = errors.into_iter().next() {
+        return error(format!("{} [line {} in '{}']", msg, token.line, name));
+    }
+
+    // Imports inside the module are relative to the module.
+    let runtime = &env.runtime;
+    let dir = resolved
+        .parent()
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+    let outer_dir = runtime.base_dir.replace(dir);
+    let result = Interpreter::new_with_env(Rc::clone(&env)).execute(statements);
+    runtime.base_dir.replace(outer_dir);
+    result.map(|_| ())
+}
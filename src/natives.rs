@@ -0,0 +1,1362 @@
+use crate::environment::Environment;
+use crate::expr::new_object;
+use crate::loxvalue::{format_number, number_key, Callable, CallableFn, InstanceValue, LoxValue};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) fn define_natives(env: &Rc<Environment>) {
+    // Seconds since the epoch, negative when the system clock is set before it.
+    define(
+        env,
+        "clock",
+        0,
+        Rc::new(|_arguments, _env| {
+            Ok(LoxValue::Number(
+                match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(since) => since.as_secs_f64(),
+                    Err(before) => -before.duration().as_secs_f64(),
+                },
+            ))
+        }),
+    );
+
+    define(
+        env,
+        "allocations",
+        0,
+        Rc::new(|_arguments, env| Ok(LoxValue::Int(env.runtime.live_allocations() as i64))),
+    );
+
+    // Bytes written by print and the output natives so far.
+    define(
+        env,
+        "output_size",
+        0,
+        Rc::new(|_arguments, env| Ok(LoxValue::Int(env.runtime.written() as i64))),
+    );
+
+    define(
+        env,
+        "script_path",
+        0,
+        Rc::new(|_arguments, env| {
+            Ok(match &*env.runtime.script.borrow() {
+                Some(path) => LoxValue::String(path.to_string_lossy().into_owned()),
+                None => LoxValue::None,
+            })
+        }),
+    );
+
+    define(
+        env,
+        "script_dir",
+        0,
+        Rc::new(|_arguments, env| {
+            Ok(
+                match env
+                    .runtime
+                    .script
+                    .borrow()
+                    .as_ref()
+                    .and_then(|path| path.parent())
+                {
+                    // A bare file name is in the working directory.
+                    Some(dir) if dir.as_os_str().is_empty() => LoxValue::String(String::from(".")),
+                    Some(dir) => LoxValue::String(dir.to_string_lossy().into_owned()),
+                    None => LoxValue::None,
+                },
+            )
+        }),
+    );
+
+    define(
+        env,
+        "sleep",
+        1,
+        Rc::new(|arguments, env| {
+            if !env.runtime.allow_sleep.get() {
+                return Err(error("sleep", "Sleeping is disabled."));
+            }
+            match arguments[0].as_number() {
+                Some(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                    let duration = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| error("sleep", "Seconds are too large."))?;
+                    thread::sleep(duration);
+                    Ok(LoxValue::None)
+                }
+                _ => Err(error("sleep", "Seconds must be a non-negative number.")),
+            }
+        }),
+    );
+
+    define(
+        env,
+        "write",
+        1,
+        Rc::new(|arguments, env| {
+            env.runtime
+                .write(&env.runtime.text(&arguments[0]))
+                .map_err(|msg| error("write", &msg))?;
+            Ok(LoxValue::None)
+        }),
+    );
+
+    define_variadic(
+        env,
+        "printf",
+        1,
+        Rc::new(|arguments, env| {
+            let mut arguments = arguments.into_iter();
+            let format = match arguments.next() {
+                Some(LoxValue::String(format)) => format,
+                _ => return Err(error("printf", "Format must be a string.")),
+            };
+            let pieces: Vec<&str> = format.split("{}").collect();
+            if pieces.len() - 1 != arguments.len() {
+                return Err(error(
+                    "printf",
+                    &format!(
+                        "Format has {} placeholder(s) but got {} argument(s).",
+                        pieces.len() - 1,
+                        arguments.len()
+                    ),
+                ));
+            }
+            let mut output = String::from(pieces[0]);
+            for (piece, argument) in pieces[1..].iter().zip(arguments) {
+                output.push_str(&env.runtime.text(&argument));
+                output.push_str(piece);
+            }
+            env.runtime
+                .write(&output)
+                .map_err(|msg| error("printf", &msg))?;
+            Ok(LoxValue::None)
+        }),
+    );
+
+    // Runs in the globals, so declarations stay around. Gives the value of an expression or of
+    // the last statement.
+    define(
+        env,
+        "eval",
+        1,
+        Rc::new(|arguments, env| {
+            if !env.runtime.allow_eval.get() {
+                return Err(error("eval", "Eval is disabled."));
+            }
+            let globals = env
+                .runtime
+                .globals
+                .borrow()
+                .upgrade()
+                .unwrap_or_else(|| Environment::globals(&env));
+            match &arguments[0] {
+                LoxValue::String(source) => eval(source, &globals),
+                _ => Err(error("eval", "Source must be a string.")),
+            }
+        }),
+    );
+
+    // Reads lines from stdin and runs them in the scope of the call until `continue` or the end of
+    // the input. Variables can be printed and set from there. Does nothing unless allowed.
+    define(
+        env,
+        "breakpoint",
+        0,
+        Rc::new(|_arguments, env| {
+            if !env.runtime.allow_breakpoint.get() {
+                return Ok(LoxValue::None);
+            }
+            let scope = env
+                .runtime
+                .caller
+                .borrow()
+                .upgrade()
+                .unwrap_or_else(|| Environment::globals(&env));
+            let write = |text: &str| {
+                env.runtime
+                    .write(text)
+                    .map_err(|msg| error("breakpoint", &msg))
+            };
+            loop {
+                write("debug> ")?;
+                let mut line = String::new();
+                // Not borrowed while the line runs, it can hit another breakpoint.
+                let read = match env.runtime.breakpoint_input.borrow_mut().as_mut() {
+                    Some(input) => input.read_line(&mut line),
+                    None => io::stdin().read_line(&mut line),
+                };
+                match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) if line.trim() == "continue" => break,
+                    Ok(_) => match eval(&line, &scope) {
+                        Ok(LoxValue::None) => {}
+                        Ok(value) => write(&format!("{}\n", value))?,
+                        Err((msg, _)) => write(&format!("{}\n", msg))?,
+                    },
+                }
+            }
+            Ok(LoxValue::None)
+        }),
+    );
+
+    // Every argument is a row, the columns are all their field names. Missing fields stay blank.
+    define_variadic(
+        env,
+        "print_table",
+        1,
+        Rc::new(|arguments, env| {
+            let mut rows = Vec::new();
+            for argument in arguments {
+                match argument {
+                    LoxValue::Instance(instance) => rows.push(instance),
+                    _ => return Err(error("print_table", "Rows must be instances.")),
+                }
+            }
+            env.runtime
+                .write(&table(&rows))
+                .map_err(|msg| error("print_table", &msg))?;
+            Ok(LoxValue::None)
+        }),
+    );
+
+    // Strings are indexed by character, not by byte.
+    define(
+        env,
+        "char_at",
+        2,
+        Rc::new(
+            |arguments, _env| match (&arguments[0], arguments[1].as_index()) {
+                (LoxValue::String(string), Some(index)) => match string.chars().nth(index) {
+                    Some(char) => Ok(LoxValue::String(char.to_string())),
+                    None => Err(error("char_at", "Index out of range.")),
+                },
+                (LoxValue::String(_), None) => Err(error(
+                    "char_at",
+                    "Index must be a non-negative whole number.",
+                )),
+                _ => Err(error("char_at", "Can only index a string.")),
+            },
+        ),
+    );
+
+    define(
+        env,
+        "char_code",
+        1,
+        Rc::new(|arguments, _env| {
+            if let LoxValue::String(string) = &arguments[0] {
+                let mut chars = string.chars();
+                if let (Some(char), None) = (chars.next(), chars.next()) {
+                    return Ok(LoxValue::Int(char as i64));
+                }
+            }
+            Err(error("char_code", "Expect a single character string."))
+        }),
+    );
+
+    define(
+        env,
+        "from_char_code",
+        1,
+        Rc::new(|arguments, _env| {
+            match arguments[0]
+                .as_index()
+                .and_then(|code| u32::try_from(code).ok())
+                .and_then(char::from_u32)
+            {
+                Some(char) => Ok(LoxValue::String(char.to_string())),
+                None => Err(error("from_char_code", "Invalid character code.")),
+            }
+        }),
+    );
+
+    // Relative to the directory of the script, like imports. Off unless the host allows I/O.
+    define(
+        env,
+        "read_file_bytes",
+        1,
+        Rc::new(|arguments, env| match &arguments[0] {
+            _ if !env.runtime.allow_io.get() => Err(error("read_file_bytes", "I/O is disabled.")),
+            LoxValue::String(path) => {
+                let path = env.runtime.base_dir.borrow().join(path);
+                match fs::read(&path) {
+                    Ok(bytes) => Ok(LoxValue::Bytes(Rc::new(RefCell::new(bytes)))),
+                    Err(e) => {
+                        let msg = format!("Could not read '{}': {}.", path.display(), e);
+                        Err(error("read_file_bytes", &msg))
+                    }
+                }
+            }
+            _ => Err(error("read_file_bytes", "Path must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "byte_at",
+        2,
+        Rc::new(
+            |arguments, _env| match (&arguments[0], arguments[1].as_index()) {
+                (LoxValue::Bytes(bytes), Some(index)) => match bytes.borrow().get(index) {
+                    Some(byte) => Ok(LoxValue::Int(*byte as i64)),
+                    None => Err(error("byte_at", "Index out of range.")),
+                },
+                (LoxValue::Bytes(_), None) => Err(error(
+                    "byte_at",
+                    "Index must be a non-negative whole number.",
+                )),
+                _ => Err(error("byte_at", "Can only index bytes.")),
+            },
+        ),
+    );
+
+    define(
+        env,
+        "string_to_bytes",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(string) => Ok(LoxValue::Bytes(Rc::new(RefCell::new(
+                string.as_bytes().to_vec(),
+            )))),
+            _ => Err(error("string_to_bytes", "Argument must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "bytes_to_string",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::Bytes(bytes) => match String::from_utf8(bytes.borrow().clone()) {
+                Ok(string) => Ok(LoxValue::String(string)),
+                Err(e) => {
+                    let msg = format!(
+                        "Bytes are not valid UTF-8 at byte {}.",
+                        e.utf8_error().valid_up_to()
+                    );
+                    Err(error("bytes_to_string", &msg))
+                }
+            },
+            _ => Err(error("bytes_to_string", "Argument must be bytes.")),
+        }),
+    );
+
+    // Encoding takes a string or bytes, decoding gives bytes as the data needn't be text.
+    define(
+        env,
+        "base64_encode",
+        1,
+        Rc::new(|arguments, _env| {
+            encoding_input("base64_encode", &arguments[0])
+                .map(|bytes| LoxValue::String(base64_encode(&bytes)))
+        }),
+    );
+
+    define(
+        env,
+        "base64_decode",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(text) => base64_decode(text)
+                .map(|bytes| LoxValue::Bytes(Rc::new(RefCell::new(bytes))))
+                .map_err(|msg| error("base64_decode", msg)),
+            _ => Err(error("base64_decode", "Argument must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "hex_encode",
+        1,
+        Rc::new(|arguments, _env| {
+            encoding_input("hex_encode", &arguments[0])
+                .map(|bytes| LoxValue::String(hex_encode(&bytes)))
+        }),
+    );
+
+    define(
+        env,
+        "hex_decode",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(text) => hex_decode(text)
+                .map(|bytes| LoxValue::Bytes(Rc::new(RefCell::new(bytes))))
+                .map_err(|msg| error("hex_decode", msg)),
+            _ => Err(error("hex_decode", "Argument must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "trim",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(string) => Ok(LoxValue::String(String::from(string.trim()))),
+            _ => Err(error("trim", "Argument must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "replace",
+        3,
+        Rc::new(
+            |arguments, _env| match (&arguments[0], &arguments[1], &arguments[2]) {
+                (LoxValue::String(_), LoxValue::String(from), LoxValue::String(_))
+                    if from.is_empty() =>
+                {
+                    Err(error("replace", "Can't replace an empty string."))
+                }
+                (LoxValue::String(string), LoxValue::String(from), LoxValue::String(to)) => {
+                    Ok(LoxValue::String(string.replace(from.as_str(), to)))
+                }
+                _ => Err(error("replace", "Arguments must be strings.")),
+            },
+        ),
+    );
+
+    define(
+        env,
+        "starts_with",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::String(string), LoxValue::String(prefix)) => {
+                Ok(LoxValue::Bool(string.starts_with(prefix.as_str())))
+            }
+            _ => Err(error("starts_with", "Arguments must be strings.")),
+        }),
+    );
+
+    define(
+        env,
+        "ends_with",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::String(string), LoxValue::String(suffix)) => {
+                Ok(LoxValue::Bool(string.ends_with(suffix.as_str())))
+            }
+            _ => Err(error("ends_with", "Arguments must be strings.")),
+        }),
+    );
+
+    // Levenshtein distance, counted in characters.
+    define(
+        env,
+        "edit_distance",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::String(a), LoxValue::String(b)) => {
+                Ok(LoxValue::Int(edit_distance(a, b) as i64))
+            }
+            _ => Err(error("edit_distance", "Arguments must be strings.")),
+        }),
+    );
+
+    define(
+        env,
+        "format_number",
+        2,
+        Rc::new(|arguments, _env| {
+            let value = match arguments[0].as_number() {
+                Some(value) => value,
+                None => return Err(error("format_number", "Value must be a number.")),
+            };
+            match arguments[1].as_index() {
+                // Far more than an f64 has, and formatting panics on huge precisions.
+                Some(decimals) if decimals > 100 => {
+                    Err(error("format_number", "Decimals must be at most 100."))
+                }
+                Some(decimals) => Ok(LoxValue::String(format_number(value, Some(decimals)))),
+                None => Err(error(
+                    "format_number",
+                    "Decimals must be a non-negative whole number.",
+                )),
+            }
+        }),
+    );
+
+    // Thousands separators in the whole part, the fraction and sign are left as they are.
+    define(
+        env,
+        "group_number",
+        2,
+        Rc::new(|arguments, _env| {
+            let text = match &arguments[0] {
+                LoxValue::Int(value) => value.to_string(),
+                LoxValue::Number(value) if value.is_finite() => format_number(*value, None),
+                _ => return Err(error("group_number", "Value must be a finite number.")),
+            };
+            match &arguments[1] {
+                LoxValue::String(separator) => Ok(LoxValue::String(group_digits(&text, separator))),
+                _ => Err(error("group_number", "Separator must be a string.")),
+            }
+        }),
+    );
+
+    // nil rather than an error, so input can be checked without failing the script.
+    define(
+        env,
+        "to_number",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(string) => Ok(parse_number(string).unwrap_or(LoxValue::None)),
+            _ => Err(error("to_number", "Argument must be a string.")),
+        }),
+    );
+
+    define(
+        env,
+        "is_number_string",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::String(string) => Ok(LoxValue::Bool(parse_number(string).is_some())),
+            _ => Err(error("is_number_string", "Argument must be a string.")),
+        }),
+    );
+
+    // Gives back one of its arguments, so ints stay ints.
+    define(
+        env,
+        "clamp",
+        3,
+        Rc::new(|mut arguments, _env| {
+            let (value, lo, hi) = match (
+                arguments[0].as_number(),
+                arguments[1].as_number(),
+                arguments[2].as_number(),
+            ) {
+                (Some(value), Some(lo), Some(hi)) => (value, lo, hi),
+                _ => return Err(error("clamp", "Arguments must be numbers.")),
+            };
+            if lo > hi {
+                return Err(error("clamp", "Lower bound must not exceed upper bound."));
+            }
+            let index = if value < lo {
+                1
+            } else if value > hi {
+                2
+            } else {
+                0
+            };
+            Ok(arguments.swap_remove(index))
+        }),
+    );
+
+    define(
+        env,
+        "lerp",
+        3,
+        Rc::new(|arguments, _env| {
+            match (
+                arguments[0].as_number(),
+                arguments[1].as_number(),
+                arguments[2].as_number(),
+            ) {
+                (Some(a), Some(b), Some(t)) => Ok(LoxValue::Number(a + (b - a) * t)),
+                _ => Err(error("lerp", "Arguments must be numbers.")),
+            }
+        }),
+    );
+
+    // nil when equal, otherwise where the values first differ and how.
+    define(
+        env,
+        "diff",
+        2,
+        Rc::new(|arguments, _env| {
+            match first_difference(&arguments[0], &arguments[1], "value", &mut vec![]) {
+                Some(difference) => Ok(LoxValue::String(difference)),
+                None => Ok(LoxValue::None),
+            }
+        }),
+    );
+
+    // A class takes the arguments of its init, a variadic callable gives its minimum.
+    define(
+        env,
+        "arity",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::Function(callable) => Ok(LoxValue::Int(callable.arity as i64)),
+            LoxValue::Class(class) => Ok(LoxValue::Int(
+                class
+                    .find_method(String::from("init"))
+                    .map_or(0, |init| init.arity) as i64,
+            )),
+            _ => Err(error(
+                "arity",
+                "Can only get the arity of functions and classes.",
+            )),
+        }),
+    );
+
+    define(
+        env,
+        "name_of",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::Function(callable) => Ok(LoxValue::String(callable.name.lexeme.clone())),
+            LoxValue::Class(class) => Ok(LoxValue::String(class.name.clone())),
+            _ => Err(error(
+                "name_of",
+                "Can only get the name of functions and classes.",
+            )),
+        }),
+    );
+
+    define(
+        env,
+        "superclass_of",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::Class(class) => Ok(class
+                .super_class
+                .clone()
+                .map_or(LoxValue::None, LoxValue::Class)),
+            _ => Err(error(
+                "superclass_of",
+                "Can only get the superclass of a class.",
+            )),
+        }),
+    );
+
+    define(
+        env,
+        "class_of",
+        1,
+        Rc::new(|arguments, _env| match &arguments[0] {
+            LoxValue::Instance(instance) => Ok(LoxValue::Class(Rc::clone(&instance.class))),
+            _ => Err(error("class_of", "Can only get the class of an instance.")),
+        }),
+    );
+
+    // The same for equal values in every run, instance fields count in any order.
+    define(
+        env,
+        "hash",
+        1,
+        Rc::new(|arguments, _env| {
+            structural_hash(&arguments[0], &mut Vec::new())
+                .map(|hash| LoxValue::Int(hash as i64))
+                .map_err(|msg| error("hash", msg))
+        }),
+    );
+
+    // profile_report().fib.calls, one object per function called so far with its calls and the
+    // seconds spent in them, callees included.
+    define(
+        env,
+        "profile_report",
+        0,
+        Rc::new(|_arguments, env| {
+            let profile = match env.runtime.profile.borrow().clone() {
+                Some(profile) => profile,
+                None => return Err(error("profile_report", "Profiling is disabled.")),
+            };
+            let object = |fields| {
+                new_object(fields, &env)
+                    .map(LoxValue::Instance)
+                    .map_err(|msg| error("profile_report", &msg))
+            };
+            let mut report = HashMap::new();
+            for (name, (calls, time)) in profile {
+                let mut entry = HashMap::new();
+                entry.insert(String::from("calls"), LoxValue::Int(calls as i64));
+                entry.insert(
+                    String::from("total_time"),
+                    LoxValue::Number(time.as_secs_f64()),
+                );
+                report.insert(name, object(entry)?);
+            }
+            object(report)
+        }),
+    );
+
+    define(
+        env,
+        "same",
+        2,
+        Rc::new(|arguments, _env| Ok(LoxValue::Bool(arguments[0].is_same(&arguments[1])))),
+    );
+
+    define(
+        env,
+        "has_field",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::Instance(instance), LoxValue::String(name)) => {
+                Ok(LoxValue::Bool(instance.fields.borrow().contains_key(name)))
+            }
+            (LoxValue::Instance(_), _) => Err(error("has_field", "Field name must be a string.")),
+            _ => Err(error("has_field", "Only instances have fields.")),
+        }),
+    );
+
+    // Whether calling the method works, inherited methods included. Only instances have methods,
+    // any other value gives false.
+    define(
+        env,
+        "responds_to",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::Instance(instance), LoxValue::String(name)) => Ok(LoxValue::Bool(
+                instance.class.find_method(name.clone()).is_some(),
+            )),
+            (_, LoxValue::String(_)) => Ok(LoxValue::Bool(false)),
+            _ => Err(error("responds_to", "Method name must be a string.")),
+        }),
+    );
+
+    // get_path(config, "server.port", 80) is config.server.port, or 80 when a field on the way
+    // is missing.
+    define_variadic(
+        env,
+        "get_path",
+        2,
+        Rc::new(|arguments, _env| {
+            let mut arguments = arguments.into_iter();
+            let mut value = arguments.next().unwrap_or(LoxValue::None);
+            let path = path_segments("get_path", arguments.next())?;
+            let default = arguments.next().unwrap_or(LoxValue::None);
+            if arguments.next().is_some() {
+                return Err(error(
+                    "get_path",
+                    "Expected an object, a path and an optional default.",
+                ));
+            }
+            for segment in path {
+                let field = match &value {
+                    LoxValue::Instance(instance) => instance.fields.borrow().get(&segment).cloned(),
+                    _ => None,
+                };
+                match field {
+                    Some(field) => value = field,
+                    None => return Ok(default),
+                }
+            }
+            Ok(value)
+        }),
+    );
+
+    // set_path(config, "server.port", 8080), missing fields on the way become empty objects.
+    define(
+        env,
+        "set_path",
+        3,
+        Rc::new(|arguments, env| {
+            let mut arguments = arguments.into_iter();
+            let mut instance = match arguments.next() {
+                Some(LoxValue::Instance(instance)) => instance,
+                _ => return Err(error("set_path", "Only instances have fields.")),
+            };
+            let mut path = path_segments("set_path", arguments.next())?;
+            let value = arguments.next().unwrap_or(LoxValue::None);
+            let last = path.pop().unwrap_or_default();
+            for segment in path {
+                let field = instance.fields.borrow().get(&segment).cloned();
+                let next = match field {
+                    Some(LoxValue::Instance(next)) => next,
+                    Some(_) => {
+                        let msg = format!("Field '{}' on the path is not an instance.", segment);
+                        return Err(error("set_path", &msg));
+                    }
+                    None => {
+                        let created = new_object(HashMap::new(), &env)
+                            .map_err(|msg| error("set_path", &msg))?;
+                        instance.set_value(segment, LoxValue::Instance(Rc::clone(&created)));
+                        created
+                    }
+                };
+                instance = next;
+            }
+            instance.set_value(last, value.clone());
+            Ok(value)
+        }),
+    );
+
+    define(
+        env,
+        "delete_field",
+        2,
+        Rc::new(|arguments, _env| match (&arguments[0], &arguments[1]) {
+            (LoxValue::Instance(instance), LoxValue::String(name)) => Ok(LoxValue::Bool(
+                instance.fields.borrow_mut().remove(name).is_some(),
+            )),
+            (LoxValue::Instance(_), _) => {
+                Err(error("delete_field", "Field name must be a string."))
+            }
+            _ => Err(error("delete_field", "Only instances have fields.")),
+        }),
+    );
+
+    // construct(Class, arguments...) is Class(arguments...) for a class only known at run time.
+    define_variadic(
+        env,
+        "construct",
+        1,
+        Rc::new(|arguments, _env| {
+            let mut arguments = arguments.into_iter();
+            let class = match arguments.next() {
+                Some(LoxValue::Class(class)) => class,
+                _ => return Err(error("construct", "Can only construct classes.")),
+            };
+            let arguments: Vec<LoxValue> = arguments.collect();
+            let arity_error = match class.methods.borrow().get("init") {
+                Some(LoxValue::Function(init)) => init.arity_error(arguments.len()),
+                _ if !arguments.is_empty() => Some(format!(
+                    "Expected 0 argument(s) but got {}.",
+                    arguments.len()
+                )),
+                _ => None,
+            };
+            if let Some(msg) = arity_error {
+                return Err(error("construct", &msg));
+            }
+            class.call(arguments)
+        }),
+    );
+
+    define(
+        env,
+        "bind",
+        2,
+        Rc::new(|arguments, env| partially_apply("bind", arguments, &env)),
+    );
+
+    define_variadic(
+        env,
+        "partial",
+        1,
+        Rc::new(|arguments, env| partially_apply("partial", arguments, &env)),
+    );
+
+    // Results are cached by arguments, which can only be nil, booleans, numbers and strings.
+    define(
+        env,
+        "memoize",
+        1,
+        Rc::new(|arguments, env| {
+            let target = match &arguments[0] {
+                LoxValue::Function(callable) => Rc::clone(callable),
+                _ => return Err(error("memoize", "Can only memoize functions.")),
+            };
+            let cache: RefCell<HashMap<Vec<CacheKey>, LoxValue>> = RefCell::new(HashMap::new());
+            let name = target.name.lexeme.clone();
+            let (arity, variadic) = (target.arity, target.variadic);
+            let function: Rc<CallableFn> = Rc::new(move |arguments, env| {
+                let key = arguments
+                    .iter()
+                    .map(CacheKey::from_value)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        error(
+                            "memoize",
+                            "Memoized functions only take nil, booleans, numbers and strings.",
+                        )
+                    })?;
+                if let Some(value) = cache.borrow().get(&key) {
+                    return Ok(value.clone());
+                }
+                // Called in a scope of its own where its name is this wrapper, so `fib = memoize(fib)`
+                // makes the recursive calls go through the cache too.
+                let name = target.name.lexeme.clone();
+                let wrapper = env.get_by_string(name.clone()).expect("Defined by call");
+                let environment = Rc::new(Environment::new_child(Rc::clone(&target.environment)));
+                environment.define(name, wrapper);
+                let value = (target.function)(arguments, environment)?;
+                cache.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            });
+            // Its own environment for the same reason as a bound function.
+            let environment = Rc::new(Environment::new_child(env));
+            Ok(LoxValue::Function(Rc::new(Callable {
+                string: format!("<memoized fn {}>", name),
+                ..native(&environment, &name, arity, variadic, function)
+            })))
+        }),
+    );
+
+    define_variadic(
+        env,
+        "invoke",
+        2,
+        Rc::new(|arguments, _env| {
+            let mut arguments = arguments.into_iter();
+            let instance = match arguments.next() {
+                Some(LoxValue::Instance(instance)) => instance,
+                _ => return Err(error("invoke", "Only instances have methods.")),
+            };
+            let name = match arguments.next() {
+                Some(LoxValue::String(name)) => name,
+                _ => return Err(error("invoke", "Method name must be a string.")),
+            };
+            let method = match instance.class.find_method(name.clone()) {
+                Some(method) => method,
+                None => return Err(error("invoke", &format!("Undefined method '{}'.", name))),
+            };
+            let arguments: Vec<LoxValue> = arguments.collect();
+            if let Some(msg) = method.arity_error(arguments.len()) {
+                return Err(error("invoke", &msg));
+            }
+            method.bind(LoxValue::Instance(instance));
+            method.call(arguments)
+        }),
+    );
+
+    // For tests of error paths, gives the message of the error `function` runs into.
+    define(
+        env,
+        "assert_throws",
+        1,
+        Rc::new(|arguments, _env| {
+            let function = match &arguments[0] {
+                LoxValue::Function(function) => function,
+                _ => return Err(error("assert_throws", "Argument must be a function.")),
+            };
+            if let Some(msg) = function.arity_error(0) {
+                return Err(error("assert_throws", &msg));
+            }
+            match function.call(Vec::new()) {
+                Ok(_) => Err(error(
+                    "assert_throws",
+                    "Expected an error but none was raised.",
+                )),
+                Err((msg, _)) => Ok(LoxValue::String(msg)),
+            }
+        }),
+    );
+}
+
+fn define(env: &Rc<Environment>, name: &str, arity: usize, function: Rc<CallableFn>) {
+    env.runtime.natives.borrow_mut().insert(String::from(name));
+    env.define(
+        String::from(name),
+        LoxValue::Function(Rc::new(native(env, name, arity, false, function))),
+    );
+}
+
+// Takes at least `arity` arguments, any extra ones are passed along.
+fn define_variadic(env: &Rc<Environment>, name: &str, arity: usize, function: Rc<CallableFn>) {
+    env.runtime.natives.borrow_mut().insert(String::from(name));
+    env.define(
+        String::from(name),
+        LoxValue::Function(Rc::new(native(env, name, arity, true, function))),
+    );
+}
+
+fn native(
+    env: &Rc<Environment>,
+    name: &str,
+    arity: usize,
+    variadic: bool,
+    function: Rc<CallableFn>,
+) -> Callable {
+    Callable {
+        arity,
+        variadic,
+        function,
+        string: "<native fn>".to_string(),
+        name: Token::synthetic(name),
+        environment: Rc::clone(env),
+        is_initializer: RefCell::new(false),
+    }
+}
+
+// Runs in `scope`, the globals for eval and the scope of the call for breakpoint.
+fn eval(source: &str, scope: &Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+    let located =
+        |msg: String, line: u64| error("eval", &format!("{} [line {} in eval]", msg, line));
+    let tokens = Scanner::new(String::from(source))
+        .scan_tokens()
+        .map_err(|(line, msg)| located(msg, line))?;
+    let result = match Parser::new(tokens.clone()).parse_expression() {
+        Ok(expression) => expression.evaluate(Rc::clone(scope)),
+        Err(_) => {
+            let (statements, errors) = Parser::new(tokens).parse();
+            if let Some((token, msg)) = errors.into_iter().next() {
+                return Err(located(msg, token.line));
+            }
+            let mut last = Ok(LoxValue::None);
+            // Like a script, so functions are hoisted and a trace hook sees every statement.
+            stmt::hoist(&statements, scope);
+            for statement in &statements {
+                last = stmt::execute(statement, Rc::clone(scope));
+                if last.is_err() {
+                    break;
+                }
+            }
+            last
+        }
+    };
+    result.map_err(|(msg, token)| located(msg, token.line))
+}
+
+// FNV-1a, std's hashers are seeded per process.
+fn fnv(tag: u8, bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in std::iter::once(&tag).chain(bytes) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn structural_hash(
+    value: &LoxValue,
+    visiting: &mut Vec<*const InstanceValue>,
+) -> Result<u64, &'static str> {
+    Ok(match value {
+        LoxValue::None => fnv(0, &[]),
+        LoxValue::Bool(a) => fnv(1, &[*a as u8]),
+        LoxValue::Int(a) => fnv(2, &a.to_le_bytes()),
+        // 2.0 == 2, so it hashes like the integer.
+        LoxValue::Number(a) if LoxValue::Number(*a) == LoxValue::Int(*a as i64) => {
+            fnv(2, &(*a as i64).to_le_bytes())
+        }
+        LoxValue::Number(a) => fnv(3, &number_key(*a).to_le_bytes()),
+        LoxValue::String(a) => fnv(4, a.as_bytes()),
+        LoxValue::Bytes(a) => fnv(6, &a.borrow()),
+        LoxValue::Instance(instance) => {
+            let pointer = Rc::as_ptr(instance);
+            if visiting.contains(&pointer) {
+                return Err("Can't hash an instance that contains itself.");
+            }
+            visiting.push(pointer);
+            let mut hash = fnv(5, instance.class.name.as_bytes());
+            for (name, field) in instance.fields.borrow().iter() {
+                let field_hash =
+                    fnv(4, name.as_bytes()).rotate_left(1) ^ structural_hash(field, visiting)?;
+                hash = hash.wrapping_add(field_hash.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            }
+            visiting.pop();
+            hash
+        }
+        _ => return Err("Can only hash nil, booleans, numbers, strings, bytes and instances."),
+    })
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum CacheKey {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    // By `number_key`, so a NaN argument finds its cached result.
+    Number(u64),
+    String(String),
+}
+
+impl CacheKey {
+    fn from_value(value: &LoxValue) -> Option<CacheKey> {
+        match value {
+            LoxValue::None => Some(CacheKey::Nil),
+            LoxValue::Bool(a) => Some(CacheKey::Bool(*a)),
+            LoxValue::Int(a) => Some(CacheKey::Int(*a)),
+            // 2.0 == 2, so it is the same key.
+            LoxValue::Number(a) if LoxValue::Number(*a) == LoxValue::Int(*a as i64) => {
+                Some(CacheKey::Int(*a as i64))
+            }
+            LoxValue::Number(a) => Some(CacheKey::Number(number_key(*a))),
+            LoxValue::String(a) => Some(CacheKey::String(a.clone())),
+            _ => None,
+        }
+    }
+}
+
+fn encoding_input(native_name: &str, value: &LoxValue) -> Result<Vec<u8>, (String, Token)> {
+    match value {
+        LoxValue::String(string) => Ok(string.as_bytes().to_vec()),
+        LoxValue::Bytes(bytes) => Ok(bytes.borrow().clone()),
+        _ => Err(error(native_name, "Argument must be a string or bytes.")),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard alphabet with '=' padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.chunks(3).len() * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, &'static str> {
+    let text = text.as_bytes();
+    if !text.chunks_exact(4).remainder().is_empty() {
+        return Err("Base64 length must be a multiple of 4.");
+    }
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (chunk_index, chunk) in text.chunks(4).enumerate() {
+        let last = chunk_index == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err("Invalid base64 padding.");
+        }
+        let mut group = 0u32;
+        for (i, c) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64
+                .iter()
+                .position(|b| b == c)
+                .ok_or("Invalid base64 character.")?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            decoded.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Either case, no separators.
+// Checked digit by digit first, `from_str_radix` would take a sign like "+f".
+fn hex_decode(text: &str) -> Result<Vec<u8>, &'static str> {
+    if !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err("Invalid hex digit.");
+    }
+    if !text.as_bytes().chunks_exact(2).remainder().is_empty() {
+        return Err("Hex length must be even.");
+    }
+    let digit = |byte: u8| (byte as char).to_digit(16).expect("Checked") as u8;
+    Ok(text
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| digit(pair[0]) << 4 | digit(pair[1]))
+        .collect())
+}
+
+// A callable taking the rest of the arguments, the bound ones go in front of them.
+fn partially_apply(
+    native_name: &str,
+    arguments: Vec<LoxValue>,
+    env: &Rc<Environment>,
+) -> Result<LoxValue, (String, Token)> {
+    let mut arguments = arguments.into_iter();
+    let target = arguments.next().expect("Checked");
+    let bound: Vec<LoxValue> = arguments.collect();
+    let (arity, variadic, name) = match &target {
+        LoxValue::Function(callable) => (
+            callable.arity,
+            callable.variadic,
+            callable.name.lexeme.clone(),
+        ),
+        LoxValue::Class(class) => (
+            class
+                .find_method(String::from("init"))
+                .map_or(0, |init| init.arity),
+            false,
+            class.name.clone(),
+        ),
+        _ => {
+            return Err(error(
+                native_name,
+                "Can only bind arguments to functions and classes.",
+            ))
+        }
+    };
+    if bound.len() > arity && !variadic {
+        return Err(error(
+            native_name,
+            &format!(
+                "Can't bind {} argument(s) to {} taking {}.",
+                bound.len(),
+                name,
+                arity
+            ),
+        ));
+    }
+    let remaining = arity.saturating_sub(bound.len());
+    let function: Rc<CallableFn> = Rc::new(move |arguments, _env| {
+        let mut all = bound.clone();
+        all.extend(arguments);
+        match &target {
+            LoxValue::Function(callable) => callable.call(all),
+            LoxValue::Class(class) => class.call(all),
+            _ => unreachable!("Checked when binding"),
+        }
+    });
+    // Its own environment, a call defines the callable's name there and must not replace the
+    // original function in the globals.
+    let environment = Rc::new(Environment::new_child(Rc::clone(env)));
+    Ok(LoxValue::Function(Rc::new(Callable {
+        string: format!("<bound fn {}>", name),
+        ..native(&environment, &name, remaining, variadic, function)
+    })))
+}
+
+// Instances of the same class are compared field by field, anything else with ==.
+fn first_difference(
+    a: &LoxValue,
+    b: &LoxValue,
+    path: &str,
+    // Pairs of instances being compared, so cyclic fields don't recurse forever.
+    seen: &mut Vec<(*const InstanceValue, *const InstanceValue)>,
+) -> Option<String> {
+    let (x, y) = match (a, b) {
+        (LoxValue::Instance(x), LoxValue::Instance(y)) if x.class.name == y.class.name => (x, y),
+        _ if a == b => return None,
+        _ => return Some(format!("{}: {} vs {}", path, a, b)),
+    };
+    let pair = (Rc::as_ptr(x), Rc::as_ptr(y));
+    if pair.0 == pair.1 || seen.contains(&pair) {
+        return None;
+    }
+    seen.push(pair);
+
+    let (x_fields, y_fields) = (x.fields.borrow(), y.fields.borrow());
+    let mut names: Vec<&String> = x_fields.keys().chain(y_fields.keys()).collect();
+    names.sort();
+    names.dedup();
+    let difference = names.into_iter().find_map(|name| {
+        let path = format!("{}.{}", path, name);
+        match (x_fields.get(name), y_fields.get(name)) {
+            (Some(a), Some(b)) => first_difference(a, b, &path, seen),
+            (Some(a), None) => Some(format!("{}: {} vs missing", path, a)),
+            (None, Some(b)) => Some(format!("{}: missing vs {}", path, b)),
+            (None, None) => None,
+        }
+    });
+    seen.pop();
+    difference
+}
+
+// Number literal syntax with an optional minus, so no "inf" or "1e5" like Rust would take.
+fn parse_number(string: &str) -> Option<LoxValue> {
+    let string = string.trim();
+    let digits = string.strip_prefix('-').unwrap_or(string);
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    if !is_digits(whole) || fraction.is_some_and(|fraction| !is_digits(fraction)) {
+        return None;
+    }
+    match string.parse::<i64>() {
+        Ok(int) => Some(LoxValue::Int(int)),
+        Err(_) => string.parse().ok().map(LoxValue::Number),
+    }
+}
+
+// Insertions, deletions and substitutions of characters to turn `a` into `b`, a row at a time.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// The separator between every three digits of the whole part, counted from the right.
+fn group_digits(number: &str, separator: &str) -> String {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", number),
+    };
+    let (whole, fraction) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    let mut grouped = String::from(sign);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(fraction);
+    grouped
+}
+
+// Left aligned columns in sorted order, two spaces apart and a dashed line under the header.
+fn table(rows: &[Rc<InstanceValue>]) -> String {
+    let columns: BTreeSet<String> = rows
+        .iter()
+        .flat_map(|row| row.fields.borrow().keys().cloned().collect::<Vec<_>>())
+        .collect();
+    let mut lines: Vec<Vec<String>> = vec![
+        columns.iter().cloned().collect(),
+        columns
+            .iter()
+            .map(|column| "-".repeat(column.chars().count()))
+            .collect(),
+    ];
+    for row in rows {
+        let fields = row.fields.borrow();
+        lines.push(
+            columns
+                .iter()
+                .map(|column| {
+                    fields
+                        .get(column)
+                        .map_or_else(String::new, LoxValue::stringify)
+                })
+                .collect(),
+        );
+    }
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            lines
+                .iter()
+                .map(|line| line[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut output = String::new();
+    for line in lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+// "a.b.c" as ["a", "b", "c"], every segment has to be a name.
+fn path_segments(
+    native_name: &str,
+    path: Option<LoxValue>,
+) -> Result<Vec<String>, (String, Token)> {
+    match path {
+        Some(LoxValue::String(path)) if path.split('.').all(|segment| !segment.is_empty()) => {
+            Ok(path.split('.').map(String::from).collect())
+        }
+        Some(LoxValue::String(_)) => Err(error(native_name, "Path can't have empty segments.")),
+        _ => Err(error(native_name, "Path must be a string.")),
+    }
+}
+
+fn error(name: &str, msg: &str) -> (String, Token) {
+    (String::from(msg), Token::synthetic(name))
+}
@@ -1,65 +1,214 @@
+use crate::environment::Environment;
 use crate::expr::{
-    Assign, Binary, Call, Expr, Get, Grouping, Kind, Literal, Logical, NoOp, Set, Super, This,
-    Unary, Variable,
+    Assign, Binary, Call, Conditional, Expr, Get, Grouping, Import, In, Kind, Lambda, Literal,
+    Logical, NoOp, Object, OptionalGet, Set, Super, This, Unary, Variable,
 };
 use crate::loxvalue::LoxValue;
 use crate::stmt::{
-    Block, ClassStmt, Expression, Function, If, Print, ReturnStmt, Stmt, Var, While,
+    Block, ClassStmt, Destructure, Expression, Function, If, ImportStmt, LazyConst, Print,
+    ReturnStmt, Stmt, StmtKind, Var, While,
 };
 use crate::token::Token;
 use crate::tokentype::TokenType;
+use crate::visitor::Node;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+pub type ParseError = (Token, String);
+
+// Deeper nested expressions are an error, the parser and evaluator recurse on the Rust stack.
+// Sized for a 2 MB thread in a debug build, where a level can take over 20 KB.
+pub const DEFAULT_MAX_NESTING: usize = 64;
+
 pub struct Parser {
     tokens: Vec<Token>,
+    // Returned by peek once past the end, so a stream without EOF can't make us index out of bounds.
+    eof: Token,
     current: usize,
     in_a_class: bool,
     in_a_init: bool,
     in_a_sub_class: bool,
+    // Names declared so far, innermost scope last, with the arity of functions and classes whose
+    // arity is known.
+    scopes: Vec<HashMap<String, Option<usize>>>,
+    // The `const` names of each scope in `scopes`, with their value when it is a number or string
+    // literal so reads of it can be replaced by the value.
+    constants: Vec<HashMap<String, Option<LoxValue>>>,
+    // Strict mode: using a name missing from `scopes` is an error.
+    strict: bool,
+    // Calls to a known function or class with the wrong number of arguments, these are errors
+    // unless the name gets assigned somewhere, which we only know after parsing everything.
+    arity_mismatches: Vec<(Token, String)>,
+    assigned: HashSet<String>,
+    // Global declarations of these names are warnings, errors in strict mode.
+    natives: HashSet<String>,
+    warnings: Vec<ParseError>,
+    // Errors recovered from inside blocks, these don't abort the enclosing statement.
+    errors: Vec<ParseError>,
+    block_depth: usize,
+    // Bodies of functions, methods and accessors being parsed, a return outside of them is an error.
+    function_depth: usize,
+    // Block depth of the innermost function body, its last expression can leave out the ';' to
+    // be returned.
+    body_depth: Option<usize>,
+    nesting: usize,
+    max_nesting: usize,
+    // Automatic semicolon insertion: a simple statement can end at the end of its line.
+    asi: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        let eof = Token {
+            token_type: TokenType::EOF,
+            lexeme: String::new(),
+            literal: LoxValue::None,
+            line: tokens.last().map_or(1, |token| token.line),
+            column: None,
+            source: None,
+        };
         Parser {
             tokens,
+            eof,
             current: 0,
             in_a_class: false,
             in_a_init: false,
             in_a_sub_class: false,
+            scopes: vec![HashMap::new()],
+            constants: vec![HashMap::new()],
+            strict: false,
+            arity_mismatches: Vec::new(),
+            assigned: HashSet::new(),
+            natives: HashSet::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            block_depth: 0,
+            function_depth: 0,
+            body_depth: None,
+            nesting: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            asi: false,
+        }
+    }
+
+    // Every variable has to be declared before use, `globals` are the names already defined.
+    pub(crate) fn new_strict(tokens: Vec<Token>, globals: Vec<String>) -> Self {
+        let mut parser = Parser::new(tokens);
+        parser.scopes = vec![globals.into_iter().map(|name| (name, None)).collect()];
+        parser.strict = true;
+        parser
+    }
+
+    pub fn set_max_nesting(&mut self, max_nesting: usize) {
+        self.max_nesting = max_nesting;
+    }
+
+    // Statements can end at a line break instead of a ';'. An expression still goes on over the
+    // line break when the next line can continue it.
+    pub fn set_asi(&mut self, asi: bool) {
+        self.asi = asi;
+    }
+
+    // Names of the natives, so a global declaration shadowing one can be reported.
+    pub fn set_natives(&mut self, natives: HashSet<String>) {
+        self.natives = natives;
+    }
+
+    // Problems that don't stop the program from running, available after `parse`.
+    pub fn take_warnings(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // The whole input as a single expression, without a trailing ';'.
+    pub fn parse_expression(&mut self) -> Result<Rc<dyn Expr>, ParseError> {
+        let expression = self.expression().map_err(|(msg, token)| (token, msg))?;
+        if !self.is_at_end() {
+            return Err((
+                self.peek().clone(),
+                String::from("Expect end of expression."),
+            ));
         }
+        Ok(expression)
     }
 
-    pub(crate) fn parse(&mut self) -> (Vec<Rc<dyn Stmt>>, Vec<(Token, String)>) {
+    pub fn parse(&mut self) -> (Vec<Rc<dyn Stmt>>, Vec<ParseError>) {
         let mut statements: Vec<Rc<dyn Stmt>> = Vec::new();
-        let mut errors: Vec<(Token, String)> = Vec::new();
+        self.declare_hoisted();
         while !self.is_at_end() {
             match self.declaration() {
                 Ok(statement) => statements.push(statement),
-                Err((msg, token)) => errors.push((token.clone(), msg)),
+                Err((msg, token)) => self.errors.push((token.clone(), msg)),
             }
+            // An error can leave scopes of the failed statement open.
+            self.scopes.truncate(1);
+            self.constants.truncate(1);
+            self.block_depth = 0;
+            self.function_depth = 0;
+            self.body_depth = None;
         }
-        (statements, errors)
+        for (token, msg) in std::mem::take(&mut self.arity_mismatches) {
+            if !self.assigned.contains(&token.lexeme) {
+                self.errors.push((token, msg));
+            }
+        }
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    // Warns about `if (x = 5)`, an extra pair of parentheses says the assignment is intended.
+    fn condition(&mut self) -> Result<Rc<dyn Expr>, (String, Token)> {
+        let condition = self.expression()?;
+        if let Kind::Assign(name) = condition.kind() {
+            self.warnings.push((
+                name,
+                String::from("Assignment used as a condition; did you mean '=='?"),
+            ));
+        }
+        Ok(condition)
     }
 
     fn expression(&mut self) -> Result<Rc<dyn Expr>, (String, Token)> {
-        self.assignment()
+        self.nested(Parser::assignment)
+    }
+
+    fn nested<F>(&mut self, parse: F) -> Result<Rc<dyn Expr>, (String, Token)>
+    where
+        F: FnOnce(&mut Parser) -> Result<Rc<dyn Expr>, (String, Token)>,
+    {
+        if self.nesting == self.max_nesting {
+            return Err((
+                String::from("Expression nesting too deep."),
+                self.peek().clone(),
+            ));
+        }
+        self.nesting += 1;
+        let expression = parse(self);
+        self.nesting -= 1;
+        expression
     }
 
     fn declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
-        if self.matching(&[TokenType::Class]) {
+        let declaration = if self.matching(&[TokenType::Class]) {
             self.class_declaration()
         } else if self.matching(&[TokenType::Fun]) {
             self.function("function")
         } else if self.matching(&[TokenType::Var]) {
             self.var_declaration()
+        } else if self.matching(&[TokenType::Const]) {
+            self.const_declaration()
+        } else if self.check_contextual("lazy", TokenType::Const) {
+            self.advance();
+            self.advance();
+            self.lazy_const_declaration()
+        } else if self.matching(&[TokenType::Import]) {
+            self.import_declaration()
         } else {
-            let statement = self.statement();
-            match statement {
-                Ok(_) => statement,
-                Err(e) => {
-                    self.synchronize();
-                    Err(e)
-                }
+            self.statement()
+        };
+        match declaration {
+            Ok(_) => declaration,
+            Err(e) => {
+                self.synchronize();
+                Err(e)
             }
         }
     }
@@ -69,6 +218,7 @@ impl Parser {
         let name = self
             .consume(TokenType::Identifier, String::from("Expect class name."))?
             .clone();
+        self.declare(&name);
 
         let mut super_class: Option<Rc<dyn Expr>> = None;
         if self.matching(&[TokenType::Less]) {
@@ -77,8 +227,10 @@ impl Parser {
                 TokenType::Identifier,
                 String::from("Expect superclass name."),
             )?;
+            let super_class_name = self.previous().clone();
+            self.check_declared(&super_class_name)?;
             super_class = Some(Rc::new(Variable {
-                name: self.previous().clone(),
+                name: super_class_name,
             }));
         }
 
@@ -88,8 +240,34 @@ impl Parser {
         )?;
         let mut methods: Vec<Rc<dyn Stmt>> = Vec::new();
 
+        let mut getters: Vec<Rc<dyn Stmt>> = Vec::new();
+        let mut setters: Vec<Rc<dyn Stmt>> = Vec::new();
+        let mut static_methods: Vec<Rc<dyn Stmt>> = Vec::new();
+        let mut static_blocks: Vec<Rc<dyn Stmt>> = Vec::new();
+
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            methods.push(self.function("method")?);
+            if self.check_contextual("get", TokenType::Identifier) {
+                self.advance();
+                getters.push(self.function("getter")?);
+            } else if self.check_contextual("set", TokenType::Identifier) {
+                self.advance();
+                setters.push(self.function("setter")?);
+            } else if self.check_contextual("fluent", TokenType::Identifier) {
+                self.advance();
+                methods.push(self.function("fluent method")?);
+            } else if self.check_contextual("static", TokenType::LeftBrace) {
+                self.advance();
+                self.advance();
+                self.begin_scope();
+                let statements = self.block();
+                self.end_scope();
+                static_blocks.push(Rc::new(Block::new(statements?)));
+            } else if self.check_contextual("static", TokenType::Identifier) {
+                self.advance();
+                static_methods.push(self.function("static method")?);
+            } else {
+                methods.push(self.function("method")?);
+            }
         }
 
         self.consume(
@@ -100,9 +278,22 @@ impl Parser {
         self.in_a_class = false;
         self.in_a_sub_class = false;
 
+        // Without an init of its own the arguments are ignored, so nothing to check.
+        let arity = methods.iter().find_map(|method| match method.kind() {
+            StmtKind::Function(function) if function.name.lexeme == "init" => {
+                Some(function.params.len())
+            }
+            _ => None,
+        });
+        self.declare_with_arity(&name, arity);
+
         Ok(Rc::new(ClassStmt {
             name,
             methods,
+            getters,
+            setters,
+            static_methods,
+            static_blocks,
             super_class,
         }))
     }
@@ -125,18 +316,22 @@ impl Parser {
         }
 
         if self.matching(&[TokenType::LeftBrace]) {
+            self.begin_scope();
             let statements = self.block()?;
-            return Ok(Rc::new(Block { statements }));
+            self.end_scope();
+            return Ok(Rc::new(Block::new(statements)));
         }
 
         self.expression_statement()
     }
 
     fn for_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let line = self.previous().line;
         self.consume(
             TokenType::LeftParen,
             String::from("Expect '(' after 'for'."),
         )?;
+        self.begin_scope();
         let initializer: Option<Rc<dyn Stmt>> = if self.matching(&[TokenType::SemiColon]) {
             None
         } else if self.matching(&[TokenType::Var]) {
@@ -146,7 +341,7 @@ impl Parser {
         };
 
         let condition: Option<Rc<dyn Expr>> = if !self.check(TokenType::SemiColon) {
-            Some(self.expression()?)
+            Some(self.condition()?)
         } else {
             None
         };
@@ -155,6 +350,7 @@ impl Parser {
             String::from("Expect ';' after loop condition."),
         )?;
 
+        let increment_line = self.peek().line;
         let increment: Option<Rc<dyn Expr>> = if !self.check(TokenType::RightParen) {
             Some(self.expression()?)
         } else {
@@ -168,13 +364,14 @@ impl Parser {
 
         let mut body = self.statement()?;
 
-        match increment {
-            Some(a) => {
-                body = Rc::new(Block {
-                    statements: vec![body, Rc::new(Expression { expression: a })],
-                })
-            }
-            None => {}
+        if let Some(a) = increment {
+            body = Rc::new(Block::new(vec![
+                body,
+                Rc::new(Expression {
+                    expression: a,
+                    line: increment_line,
+                }),
+            ]))
         }
 
         let condition_result = match condition {
@@ -187,23 +384,21 @@ impl Parser {
         body = Rc::new(While {
             condition: condition_result,
             body,
+            line,
         });
 
-        match initializer {
-            None => {}
-            Some(a) => {
-                body = Rc::new(Block {
-                    statements: vec![a, body],
-                })
-            }
+        if let Some(a) = initializer {
+            body = Rc::new(Block::new(vec![a, body]))
         }
+        self.end_scope();
 
         Ok(body)
     }
 
     fn if_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let line = self.previous().line;
         self.consume(TokenType::LeftParen, String::from("Expect '(' after 'if'."))?;
-        let condition = self.expression()?;
+        let condition = self.condition()?;
         self.consume(
             TokenType::RightParen,
             String::from("Expect ')' after if condition."),
@@ -220,25 +415,24 @@ impl Parser {
             condition,
             then_branch,
             else_branch,
+            line,
         }))
     }
 
     fn print_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let line = self.previous().line;
         let expression = self.expression()?;
-        let consumed = self.consume(
-            TokenType::SemiColon,
-            String::from("Expect ';' after expression."),
-        );
-        match consumed {
-            Ok(_) => Ok(Rc::new(Print { expression })),
-            Err(e) => Err(e),
-        }
+        self.terminate("Expect ';' after expression.")?;
+        Ok(Rc::new(Print { expression, line }))
     }
 
     fn return_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
         let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err((String::from("Can't return from top-level code."), keyword));
+        }
 
-        let value = if !self.check(TokenType::SemiColon) {
+        let value = if !self.check(TokenType::SemiColon) && !self.at_line_end() {
             if self.in_a_init {
                 return Err((
                     String::from("Can't return a value from an initializer."),
@@ -247,60 +441,177 @@ impl Parser {
             }
             self.expression()?
         } else {
-            Rc::new(NoOp {})
+            // `return;` is `return nil;`, falling off the end of a body gives nil too.
+            Rc::new(Literal {
+                value: LoxValue::None,
+            })
         };
-        self.consume(
-            TokenType::SemiColon,
-            String::from("Expect ';' after return value."),
-        )?;
-        Ok(Rc::new(ReturnStmt { value }))
+        self.terminate("Expect ';' after return value.")?;
+        Ok(Rc::new(ReturnStmt {
+            value,
+            line: keyword.line,
+        }))
     }
 
     fn var_declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        if self.matching(&[TokenType::LeftBrace]) {
+            return self.destructuring_declaration();
+        }
         let name = self
             .consume(TokenType::Identifier, String::from("Expect variable name."))?
             .clone();
         let to_return: Result<Rc<dyn Stmt>, (String, Token)> = if self.matching(&[TokenType::Equal])
         {
             let initializer = self.expression()?;
-            Ok(Rc::new(Var { name, initializer }))
+            self.declare(&name);
+            Ok(Rc::new(Var {
+                name,
+                initializer,
+                constant: false,
+            }))
         } else {
+            self.declare(&name);
             Ok(Rc::new(Var {
                 name,
                 initializer: Rc::new(NoOp {}),
+                constant: false,
             }))
         };
+        self.terminate("Expect ';' after var declaration.")?;
+        to_return
+    }
+
+    // Like a var but it needs an initializer and can't be assigned to. A number or string literal
+    // value is substituted for reads of the name, where it can be folded into the expression.
+    fn const_declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let name = self
+            .consume(TokenType::Identifier, String::from("Expect constant name."))?
+            .clone();
+        self.consume(
+            TokenType::Equal,
+            String::from("Expect '=' after constant name."),
+        )?;
+        let initializer = self.expression()?;
+        self.declare(&name);
+        let value = match initializer.kind() {
+            Kind::Literal(
+                value @ (LoxValue::Int(_) | LoxValue::Number(_) | LoxValue::String(_)),
+            ) => Some(value),
+            _ => None,
+        };
+        if let Some(constants) = self.constants.last_mut() {
+            constants.insert(name.lexeme.clone(), value);
+        }
+        self.terminate("Expect ';' after const declaration.")?;
+        Ok(Rc::new(Var {
+            name,
+            initializer,
+            constant: true,
+        }))
+    }
+
+    // Like a const, but the initializer only runs when the name is first read.
+    fn lazy_const_declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let name = self
+            .consume(TokenType::Identifier, String::from("Expect constant name."))?
+            .clone();
         self.consume(
-            TokenType::SemiColon,
-            String::from("Expect ';' after var declaration."),
+            TokenType::Equal,
+            String::from("Expect '=' after constant name."),
         )?;
-        to_return
+        let initializer = self.expression()?;
+        self.declare(&name);
+        // Never substituted for reads, its value isn't known until then.
+        if let Some(constants) = self.constants.last_mut() {
+            constants.insert(name.lexeme.clone(), None);
+        }
+        self.terminate("Expect ';' after const declaration.")?;
+        Ok(Rc::new(LazyConst { name, initializer }))
+    }
+
+    // var {x, y} = point; defines x and y from the fields of point.
+    fn destructuring_declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let mut names = Vec::new();
+        loop {
+            names.push(
+                self.consume(TokenType::Identifier, String::from("Expect field name."))?
+                    .clone(),
+            );
+            if !self.matching(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::RightBrace,
+            String::from("Expect '}' after field names."),
+        )?;
+        let equals = self
+            .consume(
+                TokenType::Equal,
+                String::from("Expect '=' after destructuring pattern."),
+            )?
+            .clone();
+        let initializer = self.expression()?;
+        for name in &names {
+            self.declare(name);
+        }
+        self.terminate("Expect ';' after var declaration.")?;
+        Ok(Rc::new(Destructure {
+            names,
+            equals,
+            initializer,
+        }))
+    }
+
+    fn import_declaration(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let path = self
+            .consume(
+                TokenType::String,
+                String::from("Expect path string after 'import'."),
+            )?
+            .clone();
+        self.terminate("Expect ';' after import path.")?;
+        // The imported file can redefine any global, their arities are no longer known.
+        for arity in self.scopes[0].values_mut() {
+            *arity = None;
+        }
+        Ok(Rc::new(ImportStmt { path }))
     }
 
     fn while_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let line = self.previous().line;
         self.consume(
             TokenType::LeftParen,
             String::from("Expect '(' after while."),
         )?;
-        let condition = self.expression()?;
+        let condition = self.condition()?;
         self.consume(
             TokenType::RightParen,
             String::from("Expect ')' after condition."),
         )?;
         let body = self.statement()?;
-        Ok(Rc::new(While { condition, body }))
+        Ok(Rc::new(While {
+            condition,
+            body,
+            line,
+        }))
     }
 
     fn expression_statement(&mut self) -> Result<Rc<dyn Stmt>, (String, Token)> {
+        let line = self.peek().line;
         let expression = self.expression()?;
-        let consumed = self.consume(
-            TokenType::SemiColon,
-            String::from("Expect ';' after expression."),
-        );
-        match consumed {
-            Ok(_) => Ok(Rc::new(Expression { expression })),
-            Err(e) => Err(e),
+        // `fun double(x) { x * 2 }`, a last expression without ';' is the return value.
+        if self.check(TokenType::RightBrace)
+            && self.body_depth == Some(self.block_depth)
+            && !self.in_a_init
+        {
+            return Ok(Rc::new(ReturnStmt {
+                value: expression,
+                line,
+            }));
         }
+        self.terminate("Expect ';' after expression.")?;
+        Ok(Rc::new(Expression { expression, line }))
     }
 
     fn function(&mut self, kind: &'static str) -> Result<Rc<dyn Stmt>, (String, Token)> {
@@ -312,6 +623,43 @@ impl Parser {
             self.in_a_init = true
         }
 
+        // A getter has no parameter list at all, `get area { ... }`.
+        let parameters = if kind == "getter" {
+            Vec::new()
+        } else {
+            self.parameters(kind)?
+        };
+        if kind == "setter" && parameters.len() != 1 {
+            return Err((String::from("A setter takes exactly one parameter."), name));
+        }
+        if kind == "function" {
+            self.declare_with_arity(&name, Some(parameters.len()));
+        }
+        self.begin_scope();
+        self.consume(
+            TokenType::LeftBrace,
+            format!("Expect '{{' before {} body.", kind),
+        )?;
+        for parameter in &parameters {
+            self.declare(parameter);
+        }
+        self.function_depth += 1;
+        let enclosing_body = self.body_depth.replace(self.block_depth + 1);
+        let body = self.block();
+        self.body_depth = enclosing_body;
+        self.function_depth -= 1;
+        let body = body?;
+        self.end_scope();
+        self.in_a_init = false;
+        Ok(Rc::new(Function {
+            name,
+            params: parameters.clone(),
+            body,
+            fluent: kind == "fluent method",
+        }))
+    }
+
+    fn parameters(&mut self, kind: &'static str) -> Result<Vec<Token>, (String, Token)> {
         self.consume(
             TokenType::LeftParen,
             format!("Expect '(' after {} name.", kind),
@@ -345,26 +693,24 @@ impl Parser {
             TokenType::RightParen,
             String::from("Expect ')' after parameters."),
         )?;
-        self.consume(
-            TokenType::LeftBrace,
-            format!("Expect '{{' before {} body.", kind),
-        )?;
-        let body = self.block()?;
-        self.in_a_init = false;
-        Ok(Rc::new(Function {
-            name,
-            params: parameters.clone(),
-            body,
-        }))
+        Ok(parameters)
     }
 
     fn block(&mut self) -> Result<Vec<Rc<dyn Stmt>>, (String, Token)> {
         let mut statements: Vec<Rc<dyn Stmt>> = Vec::new();
+        self.block_depth += 1;
+        self.declare_hoisted();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?)
+            // declaration already synchronized, keep going with the rest of the block.
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err((msg, token)) => self.errors.push((token, msg)),
+            }
         }
 
+        // Left before the '}' is checked, so a missing one doesn't leave the depth raised.
+        self.block_depth -= 1;
         self.consume(
             TokenType::RightBrace,
             String::from("Expect '}' after block."),
@@ -376,10 +722,19 @@ impl Parser {
         let expr = self.or()?;
         if self.matching(&[TokenType::Equal]) {
             let equals = self.previous().clone();
-            let value = self.assignment()?;
+            // Right associative, `a = obj.x = 5` assigns 5 to obj.x and then the value of that
+            // assignment, also 5, to a.
+            let value = self.nested(Parser::assignment)?;
 
             match expr.kind() {
-                Kind::Variable(name) => Ok(Rc::new(Assign { name, value })),
+                Kind::Variable(name) if self.constant(&name).is_some() => Err((
+                    format!("Can't assign to the constant '{}'.", name.lexeme),
+                    name,
+                )),
+                Kind::Variable(name) => {
+                    self.assigned.insert(name.lexeme.clone());
+                    Ok(Rc::new(Assign { name, value }))
+                }
                 Kind::Get(name, object) => Ok(Rc::new(Set {
                     object,
                     name,
@@ -430,11 +785,11 @@ impl Parser {
         while matching {
             let operator = self.previous().clone();
             let right = self.comparison()?;
-            expr = Rc::new(Binary {
+            expr = fold(Rc::new(Binary {
                 left: expr,
                 operator,
                 right,
-            });
+            }));
             matching = self.matching(&[TokenType::BangEqual, TokenType::EqualEqual]);
         }
         Ok(expr)
@@ -447,16 +802,25 @@ impl Parser {
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
+            TokenType::In,
         ];
         let mut matching = self.matching(types);
         while matching {
             let operator = self.previous().clone();
             let right = self.term()?;
-            expr = Rc::new(Binary {
-                left: expr,
-                operator,
-                right,
-            });
+            expr = if operator.token_type == TokenType::In {
+                Rc::new(In {
+                    element: expr,
+                    keyword: operator,
+                    collection: right,
+                })
+            } else {
+                fold(Rc::new(Binary {
+                    left: expr,
+                    operator,
+                    right,
+                }))
+            };
             matching = self.matching(types);
         }
         Ok(expr)
@@ -469,11 +833,11 @@ impl Parser {
         while matching {
             let operator = self.previous().clone();
             let right = self.factor()?;
-            expr = Rc::new(Binary {
+            expr = fold(Rc::new(Binary {
                 left: expr,
                 operator,
                 right,
-            });
+            }));
             matching = self.matching(types);
         }
         Ok(expr)
@@ -486,11 +850,11 @@ impl Parser {
         while matching {
             let operator = self.previous().clone();
             let right = self.unary()?;
-            expr = Rc::new(Binary {
+            expr = fold(Rc::new(Binary {
                 left: expr,
                 operator,
                 right,
-            });
+            }));
             matching = self.matching(types);
         }
         Ok(expr)
@@ -501,9 +865,8 @@ impl Parser {
         let matching = self.matching(types);
         if matching {
             let operator = self.previous().clone();
-            let right = self.unary()?;
-            let expr = Rc::new(Unary { operator, right });
-            return Ok(expr);
+            let right = self.nested(Parser::unary)?;
+            return Ok(fold(Rc::new(Unary { operator, right })));
         }
         self.call()
     }
@@ -529,6 +892,7 @@ impl Parser {
                 String::from("Expect ')' after arguments."),
             )?
             .clone();
+        self.check_arity(&callee, arguments.len());
         Ok(Rc::new(Call {
             callee,
             paren,
@@ -552,6 +916,17 @@ impl Parser {
                     name,
                     object: Rc::clone(&expr),
                 })
+            } else if self.matching(&[TokenType::QuestionDot]) {
+                let name = self
+                    .consume(
+                        TokenType::Identifier,
+                        String::from("Expect property name after '?.'."),
+                    )?
+                    .clone();
+                expr = Rc::new(OptionalGet {
+                    name,
+                    object: Rc::clone(&expr),
+                })
             } else {
                 break;
             }
@@ -623,10 +998,38 @@ impl Parser {
             };
         }
 
+        if self.matching(&[TokenType::If]) {
+            return self.conditional();
+        }
+
+        if self.matching(&[TokenType::HashLeftBrace]) {
+            return self.object();
+        }
+
+        if self.matching(&[TokenType::Backslash]) {
+            return self.lambda();
+        }
+
+        if self.matching(&[TokenType::Import]) {
+            let path = self
+                .consume(
+                    TokenType::String,
+                    String::from("Expect path string after 'import'."),
+                )?
+                .clone();
+            return Ok(Rc::new(Import { path }));
+        }
+
         if self.matching(&[TokenType::Identifier]) {
-            return Ok(Rc::new(Variable {
-                name: self.previous().clone(),
-            }));
+            let name = self.previous().clone();
+            self.check_declared(&name)?;
+            // Left alone when it is being assigned to, so that can be reported.
+            if !self.check(TokenType::Equal) {
+                if let Some(Some(value)) = self.constant(&name) {
+                    return Ok(Rc::new(Literal { value }));
+                }
+            }
+            return Ok(Rc::new(Variable { name }));
         }
 
         if self.matching(&[TokenType::LeftParen]) {
@@ -635,10 +1038,99 @@ impl Parser {
                 TokenType::RightParen,
                 String::from("Expect ')' after expression."),
             )?;
-            return Ok(Rc::new(Grouping { expression }));
+            return Ok(fold(Rc::new(Grouping { expression })));
         }
 
-        Ok(Rc::new(NoOp {}))
+        Err((String::from("Expect expression."), self.peek().clone()))
+    }
+
+    // `if` in expression position, both branches are expressions and the else is required.
+    fn conditional(&mut self) -> Result<Rc<dyn Expr>, (String, Token)> {
+        self.consume(TokenType::LeftParen, String::from("Expect '(' after 'if'."))?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            String::from("Expect ')' after if condition."),
+        )?;
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Else,
+            String::from("Expect 'else' in an if expression."),
+        )?;
+        let else_branch = self.expression()?;
+        Ok(Rc::new(Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+
+    // `\x, y -> x + y`, the body is a single expression which is returned.
+    fn lambda(&mut self) -> Result<Rc<dyn Expr>, (String, Token)> {
+        let name = self.previous().clone();
+        let mut params = Vec::new();
+        if !self.check(TokenType::Arrow) {
+            loop {
+                params.push(
+                    self.consume(
+                        TokenType::Identifier,
+                        String::from("Expect parameter name."),
+                    )?
+                    .clone(),
+                );
+                if !self.matching(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::Arrow,
+            String::from("Expect '->' after lambda parameters."),
+        )?;
+        self.begin_scope();
+        for param in &params {
+            self.declare(param);
+        }
+        let line = self.previous().line;
+        let value = self.expression();
+        self.end_scope();
+        Ok(Rc::new(Lambda {
+            function: Function {
+                name,
+                params,
+                body: vec![Rc::new(ReturnStmt {
+                    value: value?,
+                    line,
+                })],
+                fluent: false,
+            },
+        }))
+    }
+
+    // #{ x: 1, y: 2 }, an instance without a class.
+    fn object(&mut self) -> Result<Rc<dyn Expr>, (String, Token)> {
+        let brace = self.previous().clone();
+        let mut fields = Vec::new();
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let name = self
+                    .consume(TokenType::Identifier, String::from("Expect field name."))?
+                    .clone();
+                self.consume(
+                    TokenType::Colon,
+                    String::from("Expect ':' after field name."),
+                )?;
+                fields.push((name, self.expression()?));
+                if !self.matching(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::RightBrace,
+            String::from("Expect '}' after object fields."),
+        )?;
+        Ok(Rc::new(Object { brace, fields }))
     }
 
     fn matching(&mut self, types: &[TokenType]) -> bool {
@@ -652,20 +1144,52 @@ impl Parser {
     }
 
     fn consume(&mut self, ttype: TokenType, msg: String) -> Result<&Token, (String, Token)> {
-        if self.check(ttype) {
-            Ok(self.advance())
-        } else {
-            Err((msg, self.peek().clone()))
+        if self.check(ttype.clone()) {
+            return Ok(self.advance());
+        }
+        // A missing ';' belongs at the end of the line it was left off, not on the next one.
+        let previous = self.previous();
+        if ttype == TokenType::SemiColon && self.current > 0 && self.peek().line > previous.line {
+            return Err((msg, previous.clone()));
         }
+        Err((msg, self.peek().clone()))
+    }
+
+    // The ';' ending a simple statement, in ASI mode the end of the line does too.
+    fn terminate(&mut self, msg: &str) -> Result<(), (String, Token)> {
+        if self.matching(&[TokenType::SemiColon]) || self.at_line_end() {
+            return Ok(());
+        }
+        self.consume(TokenType::SemiColon, String::from(msg))
+            .map(|_| ())
+    }
+
+    // In ASI mode, whether the statement so far can end here without a ';'.
+    fn at_line_end(&self) -> bool {
+        self.asi
+            && (self.is_at_end()
+                || self.check(TokenType::RightBrace)
+                || self.peek().line > self.previous().line)
     }
 
     fn check(&self, ttype: TokenType) -> bool {
         !self.is_at_end() && (self.peek().token_type == ttype)
     }
 
+    // `get`, `set`, `fluent` and `static` are only keywords in a class body when a name follows, or
+    // a '{' for `static`, so methods can still be called get, set and static.
+    fn check_contextual(&self, word: &str, next: TokenType) -> bool {
+        self.check(TokenType::Identifier)
+            && self.peek().lexeme == word
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|token| token.token_type == next)
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
-            self.current = self.current + 1;
+            self.current += 1;
         }
 
         self.previous()
@@ -676,15 +1200,129 @@ impl Parser {
     }
 
     fn peek(&self) -> &Token {
-        //TODO out of bounds
-        &self.tokens[self.current]
+        self.tokens.get(self.current).unwrap_or(&self.eof)
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        self.current
+            .checked_sub(1)
+            .and_then(|previous| self.tokens.get(previous))
+            .unwrap_or(&self.eof)
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.constants.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        self.constants.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        self.declare_with_arity(name, None);
+    }
+
+    fn declare_with_arity(&mut self, name: &Token, arity: Option<usize>) {
+        // Reported once, a class is declared both before and after its body.
+        if self.scopes.len() == 1 && self.natives.remove(&name.lexeme) {
+            if self.strict {
+                let msg = format!(
+                    "Can't redefine the native '{}' in strict mode.",
+                    name.lexeme
+                );
+                self.errors.push((name.clone(), msg));
+            } else {
+                let msg = format!("Declaration shadows the native '{}'.", name.lexeme);
+                self.warnings.push((name.clone(), msg));
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), arity);
+        }
+        // A redeclaration is a new variable, a `const` adds itself back afterwards.
+        if let Some(constants) = self.constants.last_mut() {
+            constants.remove(&name.lexeme);
+        }
+    }
+
+    // Some when the innermost declaration of the name is a `const`, with its literal value if it
+    // has one.
+    fn constant(&self, name: &Token) -> Option<Option<LoxValue>> {
+        self.scopes
+            .iter()
+            .zip(&self.constants)
+            .rev()
+            .find(|(scope, _)| scope.contains_key(&name.lexeme))
+            .and_then(|(_, constants)| constants.get(&name.lexeme).cloned())
+    }
+
+    // Functions are hoisted to the top of their block, so in strict mode one declared further down
+    // can already be used.
+    fn declare_hoisted(&mut self) {
+        if !self.strict {
+            return;
+        }
+        let mut depth = 0;
+        let mut names = Vec::new();
+        for (i, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.token_type {
+                TokenType::LeftBrace | TokenType::HashLeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => break,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::Fun if depth == 0 => names.extend(
+                    self.tokens
+                        .get(i + 1)
+                        .filter(|name| name.token_type == TokenType::Identifier)
+                        .cloned(),
+                ),
+                _ => {}
+            }
+        }
+        for name in names {
+            self.declare(&name);
+        }
+    }
+
+    fn check_declared(&self, name: &Token) -> Result<(), (String, Token)> {
+        if self.strict
+            && !self
+                .scopes
+                .iter()
+                .any(|scope| scope.contains_key(&name.lexeme))
+        {
+            return Err((
+                format!("Undefined variable '{}'.", name.lexeme),
+                name.clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Only direct calls of a name that is a function or class here, anything else is checked
+    // when it runs.
+    fn check_arity(&mut self, callee: &Rc<dyn Expr>, count: usize) {
+        if let Kind::Variable(name) = callee.kind() {
+            let arity = self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme))
+                .copied()
+                .flatten();
+            if let Some(arity) = arity.filter(|arity| *arity != count) {
+                let msg = format!("Expected {} argument(s) but got {}.", arity, count);
+                self.arity_mismatches.push((name, msg));
+            }
+        }
     }
 
     fn synchronize(&mut self) {
+        // Leave the closing brace to the block we are in.
+        if self.block_depth > 0 && self.check(TokenType::RightBrace) {
+            return;
+        }
         self.advance();
         while !self.is_at_end() {
             if self.previous().token_type == TokenType::SemiColon {
@@ -693,13 +1331,16 @@ impl Parser {
 
             match self.peek().token_type {
                 TokenType::Class
+                | TokenType::Const
                 | TokenType::For
                 | TokenType::Fun
                 | TokenType::If
+                | TokenType::Import
                 | TokenType::Print
                 | TokenType::Return
                 | TokenType::Var
                 | TokenType::While => return,
+                TokenType::RightBrace if self.block_depth > 0 => return,
                 _ => {}
             }
 
@@ -707,3 +1348,25 @@ impl Parser {
         }
     }
 }
+
+// An operation on number and boolean literals is replaced by its result, `2 * 3` becomes `6`.
+// Strings are left alone, `"ab" * 1000000` would be built while parsing and kept in the tree.
+// Anything that fails or isn't finite is left to fail or be checked when it runs.
+fn fold(expr: Rc<dyn Expr>) -> Rc<dyn Expr> {
+    let folding = matches!(expr.kind(), Kind::Binary(_) | Kind::Unary | Kind::Grouping)
+        && expr.children().iter().all(|child| match child {
+            Node::Expr(child) => matches!(
+                child.kind(),
+                Kind::Literal(LoxValue::Int(_) | LoxValue::Number(_) | LoxValue::Bool(_))
+            ),
+            Node::Stmt(_) => false,
+        });
+    if !folding {
+        return expr;
+    }
+    match expr.evaluate(Rc::new(Environment::new())) {
+        Ok(LoxValue::Number(value)) if !value.is_finite() => expr,
+        Ok(value) => Rc::new(Literal { value }),
+        Err(_) => expr,
+    }
+}
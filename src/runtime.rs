@@ -0,0 +1,209 @@
+use crate::environment::Environment;
+use crate::loxvalue::{LoxValue, Module, NumberFormat};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+// State of the whole interpreter, every environment points to the same one.
+#[derive(Debug, Default)]
+pub struct Runtime {
+    // Every live instance holds a clone, so the strong count tells how many are alive.
+    allocations: Rc<Allocation>,
+    pub(crate) allocation_limit: Cell<Option<usize>>,
+    // Names of the natives, so declarations replacing one can be told apart from other globals.
+    pub(crate) natives: RefCell<HashSet<String>>,
+    // As passed to `run_file`, None in the REPL.
+    pub(crate) script: RefCell<Option<PathBuf>>,
+    // Imports are relative to the directory of the file doing the import.
+    pub(crate) base_dir: RefCell<PathBuf>,
+    // Canonical paths of the files run so far, each file is only run once.
+    pub(crate) imported: RefCell<HashSet<PathBuf>>,
+    // Modules by canonical path, importing one again gives the same module.
+    pub(crate) modules: RefCell<HashMap<PathBuf, Rc<Module>>>,
+    // Nesting of expressions being evaluated right now, calls included.
+    depth: Cell<usize>,
+    pub(crate) max_depth: Cell<Option<usize>>,
+    // Arithmetic producing NaN or infinity is an error instead of IEEE behaviour.
+    pub(crate) strict_math: Cell<bool>,
+    // Off unless the host enables it, a sleeping script blocks whoever embeds the interpreter.
+    pub(crate) allow_sleep: Cell<bool>,
+    // Same for reading files, a sandboxed script mustn't see the filesystem.
+    pub(crate) allow_io: Cell<bool>,
+    // Same for eval, which can do anything the script itself can't see coming.
+    pub(crate) allow_eval: Cell<bool>,
+    // Off unless there is someone at a terminal to talk to, breakpoint does nothing then.
+    pub(crate) allow_breakpoint: Cell<bool>,
+    // Scope of the latest call, where breakpoint runs what is typed. Only kept up to date while
+    // breakpoints are allowed.
+    pub(crate) caller: RefCell<Weak<Environment>>,
+    // What breakpoint reads its commands from, None for stdin.
+    pub(crate) breakpoint_input: RefCell<Option<Input>>,
+    // Of the interpreter, natives only have a snapshot of them. Weak as the globals hold the runtime.
+    pub(crate) globals: RefCell<Weak<Environment>>,
+    // Panics turned into errors aren't passed to the panic hook, off unless the host asks.
+    pub(crate) quiet_panics: Cell<bool>,
+    // Called before every statement, None unless the host wants to trace.
+    pub(crate) trace: RefCell<Option<Trace>>,
+    // Calls and time spent in them by function name, None unless profiling.
+    pub(crate) profile: RefCell<Option<HashMap<String, (u64, Duration)>>>,
+    // Everything print and the output natives write, collected here instead of going to stdout
+    // when the host captures it.
+    pub(crate) output: RefCell<Option<String>>,
+    pub(crate) number_format: Cell<NumberFormat>,
+    // Bytes written so far and how many can be written at most, None for no limit.
+    written: Cell<usize>,
+    pub(crate) output_limit: Cell<Option<usize>>,
+}
+
+type Hook = dyn FnMut(u64, &str);
+
+// Gets the line and a short description of each statement before it runs.
+pub struct Trace(Box<Hook>);
+
+impl Trace {
+    pub fn new(hook: impl FnMut(u64, &str) + 'static) -> Self {
+        Trace(Box::new(hook))
+    }
+
+    pub(crate) fn call(&mut self, line: u64, description: &str) {
+        (self.0)(line, description)
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Trace")
+    }
+}
+
+// Lines for the breakpoint prompt to read instead of stdin.
+pub struct Input(Box<dyn BufRead>);
+
+impl Input {
+    pub fn new(input: impl BufRead + 'static) -> Self {
+        Input(Box::new(input))
+    }
+
+    pub(crate) fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.0.read_line(line)
+    }
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Input")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Allocation;
+
+impl Runtime {
+    // Same settings, nothing counted yet.
+    pub(crate) fn reset(&self) -> Runtime {
+        Runtime {
+            allocations: Rc::new(Allocation),
+            allocation_limit: self.allocation_limit.clone(),
+            natives: RefCell::new(HashSet::new()),
+            script: self.script.clone(),
+            base_dir: self.base_dir.clone(),
+            imported: RefCell::new(HashSet::new()),
+            modules: RefCell::new(HashMap::new()),
+            depth: Cell::new(0),
+            max_depth: self.max_depth.clone(),
+            strict_math: self.strict_math.clone(),
+            allow_sleep: self.allow_sleep.clone(),
+            allow_io: self.allow_io.clone(),
+            allow_eval: self.allow_eval.clone(),
+            allow_breakpoint: self.allow_breakpoint.clone(),
+            caller: RefCell::new(Weak::new()),
+            breakpoint_input: RefCell::new(self.breakpoint_input.take()),
+            globals: RefCell::new(Weak::new()),
+            quiet_panics: self.quiet_panics.clone(),
+            trace: RefCell::new(self.trace.take()),
+            profile: RefCell::new(self.profile.borrow().as_ref().map(|_| HashMap::new())),
+            output: RefCell::new(self.output.take()),
+            number_format: self.number_format.clone(),
+            written: Cell::new(0),
+            output_limit: self.output_limit.clone(),
+        }
+    }
+
+    pub(crate) fn write(&self, text: &str) -> Result<(), String> {
+        let written = self.written.get() + text.len();
+        if self.output_limit.get().is_some_and(|limit| written > limit) {
+            return Err(String::from("Output limit exceeded."));
+        }
+        self.written.set(written);
+        match self.output.borrow_mut().as_mut() {
+            Some(output) => output.push_str(text),
+            None => {
+                // A closed pipe, like `rilox script.lox | head -1`, is an error of the script.
+                let mut stdout = io::stdout();
+                stdout
+                    .write_all(text.as_bytes())
+                    .and_then(|_| stdout.flush())
+                    .map_err(|error| format!("Could not write output: {}.", error))?;
+            }
+        }
+        Ok(())
+    }
+
+    // What write and printf show, strings without quotes and numbers in the number format.
+    pub(crate) fn text(&self, value: &LoxValue) -> String {
+        match value {
+            LoxValue::Number(a) => self.number_format.get().format(*a),
+            _ => value.stringify(),
+        }
+    }
+
+    pub(crate) fn written(&self) -> usize {
+        self.written.get()
+    }
+
+    pub(crate) fn record_call(&self, name: &str, elapsed: Duration) {
+        if let Some(profile) = self.profile.borrow_mut().as_mut() {
+            let (calls, time) = profile.entry(String::from(name)).or_default();
+            *calls += 1;
+            *time += elapsed;
+        }
+    }
+
+    pub(crate) fn live_allocations(&self) -> usize {
+        Rc::strong_count(&self.allocations) - 1
+    }
+
+    pub(crate) fn allocate(&self) -> Result<Rc<Allocation>, String> {
+        match self.allocation_limit.get() {
+            Some(limit) if self.live_allocations() >= limit => {
+                Err(String::from("Allocation limit exceeded."))
+            }
+            _ => Ok(Rc::clone(&self.allocations)),
+        }
+    }
+}
+
+// One level of evaluation depth, given back when dropped.
+pub(crate) struct Depth(Rc<Runtime>);
+
+impl Depth {
+    // Errors instead of going deeper than the maximum, before the Rust stack overflows.
+    pub(crate) fn enter(runtime: &Rc<Runtime>) -> Result<Depth, String> {
+        let depth = runtime.depth.get();
+        if runtime.max_depth.get().is_some_and(|max| depth >= max) {
+            return Err(String::from("Maximum evaluation depth exceeded."));
+        }
+        runtime.depth.set(depth + 1);
+        Ok(Depth(Rc::clone(runtime)))
+    }
+}
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
+}
@@ -2,23 +2,29 @@ use crate::loxvalue::LoxValue;
 use crate::token::Token;
 use crate::tokentype::TokenType;
 use phf::phf_map;
+use std::rc::Rc;
 
 pub struct Scanner {
-    source: String,
+    source: Rc<str>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    // Byte offset where the current line starts, for the columns of tokens.
+    line_start: usize,
 }
 
 static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
 "and" => TokenType::And,
 "class" => TokenType::Class,
+"const" => TokenType::Const,
 "else" => TokenType::Else,
 "false" => TokenType::False,
 "for" => TokenType::For,
 "fun" => TokenType::Fun,
 "if" => TokenType::If,
+"import" => TokenType::Import,
+"in" => TokenType::In,
 "nil" => TokenType::Nil,
 "or" => TokenType::Or,
 "print" => TokenType::Print,
@@ -33,11 +39,12 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
 impl Scanner {
     pub fn new(source: String) -> Self {
         Scanner {
-            source,
+            source: Rc::from(source),
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
         }
     }
 
@@ -52,6 +59,8 @@ impl Scanner {
             lexeme: "".to_string(),
             literal: LoxValue::None,
             line: self.line as u64,
+            column: None,
+            source: Some(Rc::clone(&self.source)),
         });
         Ok(self.tokens.to_vec())
     }
@@ -64,11 +73,20 @@ impl Scanner {
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
             ',' => self.add_token(TokenType::Comma),
+            ':' => self.add_token(TokenType::Colon),
             '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
+            '-' => {
+                let arrow = self.match_char('>');
+                self.add_token(if arrow {
+                    TokenType::Arrow
+                } else {
+                    TokenType::Minus
+                })
+            }
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::SemiColon),
             '*' => self.add_token(TokenType::Star),
+            '\\' => self.add_token(TokenType::Backslash),
             '!' => {
                 let doubled = self.match_char('=');
                 self.add_token(if doubled {
@@ -93,6 +111,20 @@ impl Scanner {
                     TokenType::Less
                 })
             }
+            '#' => {
+                if self.match_char('{') {
+                    self.add_token(TokenType::HashLeftBrace)
+                } else {
+                    return Err((self.line as u64, String::from("Unexpected character.")));
+                }
+            }
+            '?' => {
+                if self.match_char('.') {
+                    self.add_token(TokenType::QuestionDot)
+                } else {
+                    return Err((self.line as u64, String::from("Unexpected character.")));
+                }
+            }
             '>' => {
                 let doubled = self.match_char('=');
                 self.add_token(if doubled {
@@ -105,7 +137,7 @@ impl Scanner {
                 let doubled = self.match_char('/');
                 if doubled {
                     let mut next = self.peek();
-                    while next != '\n' && !self.is_at_end() {
+                    while next != '\n' && next != '\r' && !self.is_at_end() {
                         self.advance();
                         next = self.peek();
                     }
@@ -113,12 +145,23 @@ impl Scanner {
                     self.add_token(TokenType::Slash);
                 }
             }
-            ' ' | '\r' | '\t' => (),
-            '\n' => self.line = self.line + 1,
-            '"' => self.string()?,
+            ' ' | '\t' => (),
+            // \r\n counts once, on its \n.
+            '\r' if self.peek() == '\n' => (),
+            '\n' | '\r' => self.new_line(),
+            '"' if self.source[self.current..].starts_with("\"\"") => {
+                self.current += 2;
+                self.triple_string()?
+            }
+            '"' => self.string(false)?,
+            // Raw string, r"\n" stays a backslash and an n.
+            'r' if self.peek() == '"' => {
+                self.advance();
+                self.string(true)?
+            }
             ch => {
                 if is_digit(ch) {
-                    self.number();
+                    self.number(ch)?;
                 } else if is_alpha(ch) {
                     self.identifier();
                 } else {
@@ -141,29 +184,83 @@ impl Scanner {
         }
     }
 
-    fn number(&mut self) {
-        while is_digit(self.peek()) {
-            self.advance();
+    // Every form of number literal: 0x1F, 0b1010 and 0o17 integers, 1_000_000 with separators,
+    // 1.5 fractions and 1e-3 exponents.
+    fn number(&mut self, first: char) -> Result<(), (u64, String)> {
+        let error = |line: usize, msg: String| Err((line as u64, msg));
+        if first == '0' && matches!(self.peek(), 'x' | 'b' | 'o') {
+            let radix = match self.advance() {
+                'x' => 16,
+                'b' => 2,
+                _ => 8,
+            };
+            let digits = self.digits(radix);
+            if digits.is_empty() {
+                return error(self.line, String::from("Expect digits after base prefix."));
+            }
+            if is_alpha_numeric(self.peek()) {
+                return error(
+                    self.line,
+                    format!("Invalid digit '{}' in base {} number.", self.peek(), radix),
+                );
+            }
+            check_separators(&digits, radix, self.line)?;
+            return match i64::from_str_radix(&digits.replace('_', ""), radix) {
+                Ok(int) => {
+                    self.add_token_total(TokenType::Number, LoxValue::Int(int));
+                    Ok(())
+                }
+                Err(_) => error(self.line, String::from("Number is too large.")),
+            };
         }
 
+        self.digits(10);
+        let mut float = false;
         if self.peek() == '.' && is_digit(self.peek_next()) {
             self.advance();
-            while is_digit(self.peek()) {
+            self.digits(10);
+            float = true;
+        }
+        if matches!(self.peek(), 'e' | 'E') {
+            self.advance();
+            if matches!(self.peek(), '+' | '-') {
                 self.advance();
             }
+            if !is_digit(self.peek()) {
+                return error(self.line, String::from("Expect digits in exponent."));
+            }
+            self.digits(10);
+            float = true;
+        }
+        let text = &self.source[self.start..self.current];
+        check_separators(text, 10, self.line)?;
+        let number_string = text.replace('_', "");
+        // Whole numbers are exact integers unless they don't fit in one.
+        let number = match number_string.parse::<i64>() {
+            Ok(int) if !float => LoxValue::Int(int),
+            _ => LoxValue::Number(number_string.parse().unwrap()),
+        };
+        self.add_token_total(TokenType::Number, number);
+        Ok(())
+    }
+
+    // Digits in the radix and separators, the separators are checked afterwards.
+    fn digits(&mut self, radix: u32) -> String {
+        let start = self.current;
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            self.advance();
         }
-        let number_string = &self.source[self.start..self.current];
-        let number: f64 = number_string.parse().unwrap();
-        self.add_token_total(TokenType::Number, LoxValue::Number(number));
+        String::from(&self.source[start..self.current])
     }
 
-    fn string(&mut self) -> Result<(), (u64, String)> {
+    fn string(&mut self, raw: bool) -> Result<(), (u64, String)> {
+        let start_line = self.line;
         let mut peeked = self.peek();
         while peeked != '"' && !self.is_at_end() {
-            if peeked == '\n' {
-                self.line = self.line + 1;
-            }
             self.advance();
+            if ends_line(peeked, self.peek()) {
+                self.new_line();
+            }
             peeked = self.peek();
         }
 
@@ -173,7 +270,31 @@ impl Scanner {
 
         self.advance();
 
-        let value: String = String::from(&self.source[self.start + 1..self.current - 1]);
+        let value = if raw {
+            String::from(&self.source[self.start + 2..self.current - 1])
+        } else {
+            unescape(&self.source[self.start + 1..self.current - 1], start_line)?
+        };
+        self.add_token_total(TokenType::String, LoxValue::String(value));
+        Ok(())
+    }
+
+    // Between """ and """, so it can hold single quotes.
+    fn triple_string(&mut self) -> Result<(), (u64, String)> {
+        let start_line = self.line;
+        while !self.source[self.current..].starts_with("\"\"\"") && !self.is_at_end() {
+            if ends_line(self.advance(), self.peek()) {
+                self.new_line();
+            }
+        }
+
+        if self.is_at_end() {
+            return Err((self.line as u64, String::from("Unterminated string.")));
+        }
+
+        self.current += 3;
+
+        let value = unescape(&self.source[self.start + 3..self.current - 3], start_line)?;
         self.add_token_total(TokenType::String, LoxValue::String(value));
         Ok(())
     }
@@ -182,60 +303,141 @@ impl Scanner {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.peek() != expected {
             return false;
         }
-        self.current = self.current + 1;
-        return true;
+        self.current += expected.len_utf8();
+        true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 > self.source.len() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
+    // `current` is a byte offset, so slicing the source stays valid for multi-byte characters.
     fn advance(&mut self) -> char {
-        let return_char = self.source.chars().nth(self.current).unwrap(); //TODO not so nice but following along
-        self.current = self.current + 1;
+        let return_char = self.peek();
+        self.current += return_char.len_utf8();
         return_char
     }
 
+    // Called once the line end has been consumed.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn add_token(&mut self, token_type: TokenType) {
         self.add_token_total(token_type, LoxValue::None);
     }
 
     fn add_token_total(&mut self, token_type: TokenType, literal: LoxValue) {
         let text = &self.source[self.start..self.current];
+        // A token ending on a later line than it started has no column on its own line.
+        let column = (self.start >= self.line_start)
+            .then(|| self.source[self.line_start..self.start].chars().count());
         self.tokens.push(Token {
             token_type,
             lexeme: String::from(text),
             literal,
             line: self.line as u64,
+            column,
+            source: Some(Rc::clone(&self.source)),
         })
     }
 }
 
+// Decodes \xNN and \u{N...} escapes, any other backslash is kept as it is.
+fn unescape(string: &str, mut line: usize) -> Result<String, (u64, String)> {
+    let mut unescaped = String::with_capacity(string.len());
+    let mut chars = string.chars().peekable();
+    while let Some(c) = chars.next() {
+        if ends_line(c, chars.peek().copied().unwrap_or('\0')) {
+            line += 1;
+        }
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('x')) => {
+                chars.next();
+                let digits: String = chars.by_ref().take(2).collect();
+                // Checked first, from_str_radix also takes a sign.
+                if digits.len() != 2 || !is_hex(&digits) {
+                    return Err((line as u64, String::from("Invalid hex escape.")));
+                }
+                char::from(u8::from_str_radix(&digits, 16).expect("Checked"))
+            }
+            ('\\', Some('u')) => {
+                chars.next();
+                let error = || (line as u64, String::from("Invalid unicode escape."));
+                if chars.next() != Some('{') {
+                    return Err(error());
+                }
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(digit) => digits.push(digit),
+                        None => return Err(error()),
+                    }
+                }
+                if digits.is_empty() || digits.len() > 6 || !is_hex(&digits) {
+                    return Err(error());
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(error)?
+            }
+            _ => c,
+        };
+        unescaped.push(escaped);
+    }
+    Ok(unescaped)
+}
+
+fn is_hex(digits: &str) -> bool {
+    digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// A '_' needs a digit on both sides, so no leading, trailing or doubled separators.
+fn check_separators(text: &str, radix: u32, line: usize) -> Result<(), (u64, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        let digit_at = |index: Option<usize>| {
+            index
+                .and_then(|index| chars.get(index))
+                .is_some_and(|c| c.is_digit(radix))
+        };
+        if *c == '_' && !(digit_at(i.checked_sub(1)) && digit_at(Some(i + 1))) {
+            return Err((
+                line as u64,
+                String::from("Digit separator '_' must be between digits."),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// A \n, or a \r on its own as old Mac files use, \r\n is a single line end.
+fn ends_line(c: char, next: char) -> bool {
+    c == '\n' || (c == '\r' && next != '\n')
+}
+
 fn is_alpha_numeric(c: char) -> bool {
     is_alpha(c) || is_digit(c)
 }
 
 fn is_alpha(c: char) -> bool {
-    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+    c.is_ascii_lowercase() || c.is_ascii_uppercase() || c == '_'
 }
 
 fn is_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
@@ -0,0 +1,434 @@
+use crate::expr::{
+    Assign, Binary, Call, Conditional, Expr, Get, Grouping, Import, In, Lambda, Literal, Logical,
+    NoOp, Object, OptionalGet, Set, Super, This, Unary, Variable,
+};
+use crate::loxvalue::LoxValue;
+use crate::stmt::{
+    Block, ClassStmt, Destructure, Expression, Function, If, ImportStmt, LazyConst, Print,
+    ReturnStmt, Stmt, Var, While,
+};
+use crate::token::Token;
+use crate::tokentype::TokenType;
+use std::rc::Rc;
+
+// The parsed program as s-expressions, one statement per line, so it can be stored and run again
+// without scanning and parsing the source. Tokens keep their type, lexeme, literal and line but
+// not their column or source, errors from a loaded program don't show the source line.
+pub fn to_sexpr(statements: &[Rc<dyn Stmt>]) -> String {
+    statements
+        .iter()
+        .map(|statement| format!("{}\n", statement.to_sexpr()))
+        .collect()
+}
+
+pub fn from_sexpr(text: &str) -> Result<Vec<Rc<dyn Stmt>>, String> {
+    let mut reader = Reader {
+        chars: text.chars().peekable(),
+    };
+    let mut statements = Vec::new();
+    while let Some(sexpr) = reader.read()? {
+        statements.push(stmt(&sexpr)?);
+    }
+    Ok(statements)
+}
+
+pub(crate) fn write_token(token: &Token) -> String {
+    format!(
+        "(token {:?} {} {} {})",
+        token.token_type,
+        write_string(&token.lexeme),
+        write_value(&token.literal),
+        token.line
+    )
+}
+
+pub(crate) fn write_value(value: &LoxValue) -> String {
+    match value {
+        LoxValue::String(a) => format!("(str {})", write_string(a)),
+        // Debug gives back exactly the same float when read.
+        LoxValue::Number(a) => format!("(num {:?})", a),
+        LoxValue::Int(a) => format!("(int {})", a),
+        LoxValue::Bool(a) => a.to_string(),
+        // The parser only puts strings, numbers, booleans and nil in literals and tokens.
+        _ => String::from("nil"),
+    }
+}
+
+pub(crate) fn write_string(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Space separated, for the statements of a block or a body.
+pub(crate) fn write_stmts(statements: &[Rc<dyn Stmt>]) -> String {
+    statements
+        .iter()
+        .map(|statement| statement.to_sexpr())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+enum Sexpr {
+    Atom(String),
+    String(String),
+    List(Vec<Sexpr>),
+}
+
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Reader<'_> {
+    // None at the end of the text.
+    fn read(&mut self) -> Result<Option<Sexpr>, String> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match self.chars.next() {
+            None => Ok(None),
+            Some('(') => {
+                let mut items = Vec::new();
+                loop {
+                    while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if self.chars.next_if_eq(&')').is_some() {
+                        return Ok(Some(Sexpr::List(items)));
+                    }
+                    match self.read()? {
+                        Some(item) => items.push(item),
+                        None => return Err(String::from("Expect ')' before the end.")),
+                    }
+                }
+            }
+            Some(')') => Err(String::from("Unexpected ')'.")),
+            Some('"') => {
+                let mut string = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('"') => return Ok(Some(Sexpr::String(string))),
+                        Some('\\') => match self.chars.next() {
+                            Some(c) => string.push(c),
+                            None => break,
+                        },
+                        Some(c) => string.push(c),
+                        None => break,
+                    }
+                }
+                Err(String::from("Unterminated string."))
+            }
+            Some(c) => {
+                let mut atom = String::from(c);
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| !c.is_whitespace() && *c != '(' && *c != ')')
+                {
+                    atom.push(c);
+                }
+                Ok(Some(Sexpr::Atom(atom)))
+            }
+        }
+    }
+}
+
+// The name at the head of a list and the items after it.
+fn form(sexpr: &Sexpr) -> Result<(&str, &[Sexpr]), String> {
+    match sexpr {
+        Sexpr::List(items) => match items.split_first() {
+            Some((Sexpr::Atom(head), rest)) => Ok((head.as_str(), rest)),
+            _ => Err(String::from("Expect a name at the start of a list.")),
+        },
+        _ => Err(String::from("Expect a list.")),
+    }
+}
+
+fn list(sexpr: &Sexpr) -> Result<&[Sexpr], String> {
+    match sexpr {
+        Sexpr::List(items) => Ok(items.as_slice()),
+        _ => Err(String::from("Expect a list.")),
+    }
+}
+
+fn atom(sexpr: &Sexpr) -> Result<&str, String> {
+    match sexpr {
+        Sexpr::Atom(atom) => Ok(atom.as_str()),
+        _ => Err(String::from("Expect an atom.")),
+    }
+}
+
+fn string(sexpr: &Sexpr) -> Result<String, String> {
+    match sexpr {
+        Sexpr::String(string) => Ok(string.clone()),
+        _ => Err(String::from("Expect a string.")),
+    }
+}
+
+fn line(sexpr: &Sexpr) -> Result<u64, String> {
+    let line = atom(sexpr)?;
+    line.parse()
+        .map_err(|_| format!("Invalid line '{}'.", line))
+}
+
+fn boolean(sexpr: &Sexpr) -> Result<bool, String> {
+    match atom(sexpr)? {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!("Invalid boolean '{}'.", other)),
+    }
+}
+
+fn value(sexpr: &Sexpr) -> Result<LoxValue, String> {
+    if let Sexpr::Atom(atom) = sexpr {
+        return match atom.as_str() {
+            "nil" => Ok(LoxValue::None),
+            "true" => Ok(LoxValue::Bool(true)),
+            "false" => Ok(LoxValue::Bool(false)),
+            other => Err(format!("Invalid value '{}'.", other)),
+        };
+    }
+    match form(sexpr)? {
+        ("str", [a]) => Ok(LoxValue::String(string(a)?)),
+        ("num", [a]) => atom(a)?
+            .parse()
+            .map(LoxValue::Number)
+            .map_err(|_| String::from("Invalid number.")),
+        ("int", [a]) => atom(a)?
+            .parse()
+            .map(LoxValue::Int)
+            .map_err(|_| String::from("Invalid integer.")),
+        (head, _) => Err(format!("Invalid value '{}'.", head)),
+    }
+}
+
+fn token(sexpr: &Sexpr) -> Result<Token, String> {
+    match form(sexpr)? {
+        ("token", [token_type, lexeme, literal, token_line]) => Ok(Token {
+            token_type: self::token_type(atom(token_type)?)?,
+            lexeme: string(lexeme)?,
+            literal: value(literal)?,
+            line: line(token_line)?,
+            column: None,
+            source: None,
+        }),
+        (head, _) => Err(format!("Invalid token '{}'.", head)),
+    }
+}
+
+fn tokens(sexpr: &Sexpr) -> Result<Vec<Token>, String> {
+    list(sexpr)?.iter().map(token).collect()
+}
+
+fn stmts(sexprs: &[Sexpr]) -> Result<Vec<Rc<dyn Stmt>>, String> {
+    sexprs.iter().map(stmt).collect()
+}
+
+fn function(sexpr: &Sexpr) -> Result<Function, String> {
+    match form(sexpr)? {
+        ("function", [name, fluent, params, body @ ..]) => Ok(Function {
+            name: token(name)?,
+            params: tokens(params)?,
+            body: stmts(body)?,
+            fluent: boolean(fluent)?,
+        }),
+        (head, _) => Err(format!("Invalid function '{}'.", head)),
+    }
+}
+
+fn stmt(sexpr: &Sexpr) -> Result<Rc<dyn Stmt>, String> {
+    let statement: Rc<dyn Stmt> = match form(sexpr)? {
+        ("expression", [statement_line, expression]) => Rc::new(Expression {
+            expression: expr(expression)?,
+            line: line(statement_line)?,
+        }),
+        ("print", [statement_line, expression]) => Rc::new(Print {
+            expression: expr(expression)?,
+            line: line(statement_line)?,
+        }),
+        (keyword @ ("var" | "const"), [name, initializer]) => Rc::new(Var {
+            name: token(name)?,
+            initializer: expr(initializer)?,
+            constant: keyword == "const",
+        }),
+        ("lazy_const", [name, initializer]) => Rc::new(LazyConst {
+            name: token(name)?,
+            initializer: expr(initializer)?,
+        }),
+        ("destructure", [names, equals, initializer]) => Rc::new(Destructure {
+            names: tokens(names)?,
+            equals: token(equals)?,
+            initializer: expr(initializer)?,
+        }),
+        ("block", statements) => Rc::new(Block::new(stmts(statements)?)),
+        ("if", [statement_line, condition, then_branch]) => Rc::new(If {
+            condition: expr(condition)?,
+            then_branch: stmt(then_branch)?,
+            else_branch: None,
+            line: line(statement_line)?,
+        }),
+        ("if", [statement_line, condition, then_branch, else_branch]) => Rc::new(If {
+            condition: expr(condition)?,
+            then_branch: stmt(then_branch)?,
+            else_branch: Some(stmt(else_branch)?),
+            line: line(statement_line)?,
+        }),
+        ("while", [statement_line, condition, body]) => Rc::new(While {
+            condition: expr(condition)?,
+            body: stmt(body)?,
+            line: line(statement_line)?,
+        }),
+        ("function", _) => Rc::new(function(sexpr)?),
+        ("return", [statement_line, value]) => Rc::new(ReturnStmt {
+            value: expr(value)?,
+            line: line(statement_line)?,
+        }),
+        (
+            "class",
+            [name, super_class, methods, getters, setters, static_methods, static_blocks],
+        ) => Rc::new(ClassStmt {
+            name: token(name)?,
+            methods: stmts(list(methods)?)?,
+            getters: stmts(list(getters)?)?,
+            setters: stmts(list(setters)?)?,
+            static_methods: stmts(list(static_methods)?)?,
+            static_blocks: stmts(list(static_blocks)?)?,
+            super_class: match super_class {
+                Sexpr::Atom(nil) if nil == "nil" => None,
+                super_class => Some(expr(super_class)?),
+            },
+        }),
+        ("import", [path]) => Rc::new(ImportStmt { path: token(path)? }),
+        (head, _) => return Err(format!("Invalid statement '{}'.", head)),
+    };
+    Ok(statement)
+}
+
+fn expr(sexpr: &Sexpr) -> Result<Rc<dyn Expr>, String> {
+    let expression: Rc<dyn Expr> = match form(sexpr)? {
+        ("binary", [left, operator, right]) => Rc::new(Binary {
+            left: expr(left)?,
+            operator: token(operator)?,
+            right: expr(right)?,
+        }),
+        ("in", [element, keyword, collection]) => Rc::new(In {
+            element: expr(element)?,
+            keyword: token(keyword)?,
+            collection: expr(collection)?,
+        }),
+        ("conditional", [condition, then_branch, else_branch]) => Rc::new(Conditional {
+            condition: expr(condition)?,
+            then_branch: expr(then_branch)?,
+            else_branch: expr(else_branch)?,
+        }),
+        ("object", [brace, fields @ ..]) => Rc::new(Object {
+            brace: token(brace)?,
+            fields: fields
+                .iter()
+                .map(|field| match form(field)? {
+                    ("field", [name, value]) => Ok((token(name)?, expr(value)?)),
+                    (head, _) => Err(format!("Invalid field '{}'.", head)),
+                })
+                .collect::<Result<_, String>>()?,
+        }),
+        ("grouping", [expression]) => Rc::new(Grouping {
+            expression: expr(expression)?,
+        }),
+        ("literal", [literal]) => Rc::new(Literal {
+            value: value(literal)?,
+        }),
+        ("unary", [operator, right]) => Rc::new(Unary {
+            operator: token(operator)?,
+            right: expr(right)?,
+        }),
+        ("variable", [name]) => Rc::new(Variable { name: token(name)? }),
+        ("noop", []) => Rc::new(NoOp {}),
+        ("assign", [name, value]) => Rc::new(Assign {
+            name: token(name)?,
+            value: expr(value)?,
+        }),
+        ("logical", [left, operator, right]) => Rc::new(Logical {
+            left: expr(left)?,
+            operator: token(operator)?,
+            right: expr(right)?,
+        }),
+        ("call", [callee, paren, arguments @ ..]) => Rc::new(Call {
+            callee: expr(callee)?,
+            paren: token(paren)?,
+            arguments: arguments.iter().map(expr).collect::<Result<_, String>>()?,
+        }),
+        ("get", [object, name]) => Rc::new(Get {
+            object: expr(object)?,
+            name: token(name)?,
+        }),
+        ("optional_get", [object, name]) => Rc::new(OptionalGet {
+            object: expr(object)?,
+            name: token(name)?,
+        }),
+        ("set", [object, name, value]) => Rc::new(Set {
+            object: expr(object)?,
+            name: token(name)?,
+            value: expr(value)?,
+        }),
+        ("this", [keyword]) => Rc::new(This {
+            keyword: token(keyword)?,
+        }),
+        ("super", [keyword, method]) => Rc::new(Super {
+            keyword: token(keyword)?,
+            method: token(method)?,
+        }),
+        ("lambda", [lambda]) => Rc::new(Lambda {
+            function: function(lambda)?,
+        }),
+        ("import", [path]) => Rc::new(Import { path: token(path)? }),
+        (head, _) => return Err(format!("Invalid expression '{}'.", head)),
+    };
+    Ok(expression)
+}
+
+// The names `write_token` gives, which are those of the variants.
+fn token_type(name: &str) -> Result<TokenType, String> {
+    Ok(match name {
+        "LeftParen" => TokenType::LeftParen,
+        "RightParen" => TokenType::RightParen,
+        "LeftBrace" => TokenType::LeftBrace,
+        "RightBrace" => TokenType::RightBrace,
+        "Comma" => TokenType::Comma,
+        "Colon" => TokenType::Colon,
+        "Dot" => TokenType::Dot,
+        "Minus" => TokenType::Minus,
+        "Plus" => TokenType::Plus,
+        "SemiColon" => TokenType::SemiColon,
+        "Slash" => TokenType::Slash,
+        "Star" => TokenType::Star,
+        "Backslash" => TokenType::Backslash,
+        "Bang" => TokenType::Bang,
+        "BangEqual" => TokenType::BangEqual,
+        "Equal" => TokenType::Equal,
+        "EqualEqual" => TokenType::EqualEqual,
+        "Greater" => TokenType::Greater,
+        "GreaterEqual" => TokenType::GreaterEqual,
+        "Less" => TokenType::Less,
+        "LessEqual" => TokenType::LessEqual,
+        "QuestionDot" => TokenType::QuestionDot,
+        "HashLeftBrace" => TokenType::HashLeftBrace,
+        "Arrow" => TokenType::Arrow,
+        "Identifier" => TokenType::Identifier,
+        "String" => TokenType::String,
+        "Number" => TokenType::Number,
+        "And" => TokenType::And,
+        "Class" => TokenType::Class,
+        "Const" => TokenType::Const,
+        "Else" => TokenType::Else,
+        "False" => TokenType::False,
+        "Fun" => TokenType::Fun,
+        "For" => TokenType::For,
+        "If" => TokenType::If,
+        "Import" => TokenType::Import,
+        "In" => TokenType::In,
+        "Nil" => TokenType::Nil,
+        "Or" => TokenType::Or,
+        "Print" => TokenType::Print,
+        "Return" => TokenType::Return,
+        "Super" => TokenType::Super,
+        "This" => TokenType::This,
+        "True" => TokenType::True,
+        "Var" => TokenType::Var,
+        "While" => TokenType::While,
+        "EOF" => TokenType::EOF,
+        other => return Err(format!("Invalid token type '{}'.", other)),
+    })
+}
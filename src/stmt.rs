@@ -1,16 +1,27 @@
 use crate::environment::Environment;
 use crate::expr::{is_truthy, Expr, Kind};
 use crate::interpreter::Interpreter;
-use crate::loxvalue::{Callable, Class, LoxValue};
+use crate::loxvalue::{Callable, Class, Lazy, LoxValue};
+use crate::module;
+use crate::sexpr::{write_stmts, write_token};
 use crate::token::Token;
+use crate::visitor::Node;
 use std::borrow::Borrow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
 pub trait Stmt {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)>;
     fn kind(&self) -> StmtKind;
+    // Where the statement starts, for tracing.
+    fn line(&self) -> u64;
+    // For `sexpr::to_sexpr`, in the form `sexpr::from_sexpr` reads back.
+    fn to_sexpr(&self) -> String;
+    // The expressions and statements inside, for visitors.
+    fn children(&self) -> Vec<Node<'_>> {
+        Vec::new()
+    }
 }
 
 pub enum StmtKind {
@@ -23,10 +34,54 @@ pub enum StmtKind {
     Function(Function),
     ReturnStmt,
     ClassStmt,
+    Import,
+}
+
+// Evaluates the statement, calling the trace hook of the runtime first if there is one.
+pub(crate) fn execute(
+    statement: &Rc<dyn Stmt>,
+    env: Rc<Environment>,
+) -> Result<LoxValue, (String, Token)> {
+    if let Some(trace) = env.runtime.trace.borrow_mut().as_mut() {
+        trace.call(statement.line(), &describe(statement.as_ref()));
+    }
+    statement.evaluate(env)
+}
+
+// Functions can be called before their declaration in the same block or body. They are defined
+// up front here, in a scope of their own on `env` itself rather than a snapshot of it, so a call
+// sees the variables declared before it. Their declaration defines them again when it runs.
+// Classes aren't hoisted, their superclass and static blocks have to run in order.
+pub(crate) fn hoist(statements: &[Rc<dyn Stmt>], env: &Rc<Environment>) {
+    for statement in statements {
+        if let StmtKind::Function(function) = statement.kind() {
+            let scope = Rc::new(Environment::new_child(Rc::clone(env)));
+            let name = function.name.lexeme.clone();
+            let callable = function.callable(&scope, format!("<fn {}>", name));
+            env.define(name, LoxValue::Function(callable));
+        }
+    }
+}
+
+// Short enough to show on every traced line.
+fn describe(statement: &dyn Stmt) -> String {
+    match statement.kind() {
+        StmtKind::Expression => String::from("expression"),
+        StmtKind::Print => String::from("print"),
+        StmtKind::Var => String::from("var"),
+        StmtKind::Block => String::from("block"),
+        StmtKind::If => String::from("if"),
+        StmtKind::While => String::from("while"),
+        StmtKind::Function(function) => format!("fun {}", function.name.lexeme),
+        StmtKind::ReturnStmt => String::from("return"),
+        StmtKind::ClassStmt => String::from("class"),
+        StmtKind::Import => String::from("import"),
+    }
 }
 
 pub struct Expression {
     pub(crate) expression: Rc<dyn Expr>,
+    pub(crate) line: u64,
 }
 
 impl Stmt for Expression {
@@ -34,102 +89,314 @@ impl Stmt for Expression {
         self.expression.evaluate(env)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.expression.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::Expression
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(expression {} {})", self.line, self.expression.to_sexpr())
+    }
 }
 
 pub struct Print {
     pub(crate) expression: Rc<dyn Expr>,
+    pub(crate) line: u64,
 }
 
 impl Stmt for Print {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
-        match self.expression.evaluate(env) {
+        match self.expression.evaluate(Rc::clone(&env)) {
             Ok(value) => {
-                println!("{}", value);
+                let format = env.runtime.number_format.get();
+                env.runtime
+                    .write(&format!("{}\n", value.display(&format)))
+                    .map_err(|msg| {
+                        let mut keyword = Token::synthetic("print");
+                        keyword.line = self.line;
+                        (msg, keyword)
+                    })?;
                 Ok(LoxValue::None)
             }
             Err(e) => Err(e),
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.expression.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::Print
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(print {} {})", self.line, self.expression.to_sexpr())
+    }
 }
 
 pub struct Var {
     pub(crate) name: Token,
     pub(crate) initializer: Rc<dyn Expr>,
+    // Declared with const.
+    pub(crate) constant: bool,
 }
 
 impl Stmt for Var {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
         let val = self.initializer.evaluate(Rc::clone(&env))?;
-        env.define(self.name.lexeme.clone(), val.clone());
+        if self.constant {
+            env.define_constant(self.name.lexeme.clone(), val.clone());
+        } else {
+            env.define(self.name.lexeme.clone(), val.clone());
+        }
         Ok(val.clone())
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.initializer.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.name.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::Var
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "({} {} {})",
+            if self.constant { "const" } else { "var" },
+            write_token(&self.name),
+            self.initializer.to_sexpr()
+        )
+    }
+}
+
+// `lazy const name = initializer;`, defines the name without running the initializer yet.
+pub struct LazyConst {
+    pub(crate) name: Token,
+    pub(crate) initializer: Rc<dyn Expr>,
+}
+
+impl Stmt for LazyConst {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let lazy = Lazy {
+            name: self.name.clone(),
+            initializer: Rc::clone(&self.initializer),
+            environment: Rc::clone(&env),
+            value: RefCell::new(None),
+            running: Cell::new(false),
+        };
+        env.define_constant(self.name.lexeme.clone(), LoxValue::Lazy(Rc::new(lazy)));
+        Ok(LoxValue::None)
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.initializer.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.name.line
+    }
+
+    fn kind(&self) -> StmtKind {
+        StmtKind::Var
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(lazy_const {} {})",
+            write_token(&self.name),
+            self.initializer.to_sexpr()
+        )
+    }
+}
+
+pub struct Destructure {
+    pub(crate) names: Vec<Token>,
+    pub(crate) equals: Token,
+    pub(crate) initializer: Rc<dyn Expr>,
+}
+
+impl Stmt for Destructure {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let instance = match self.initializer.evaluate(Rc::clone(&env))? {
+            LoxValue::Instance(instance) => instance,
+            _ => {
+                return Err((
+                    String::from("Only instances can be destructured."),
+                    self.equals.clone(),
+                ))
+            }
+        };
+        for name in &self.names {
+            env.define(name.lexeme.clone(), instance.get_value(name)?);
+        }
+        Ok(LoxValue::None)
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.initializer.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.equals.line
+    }
+
+    // Declares variables just like a var, so blocks holding one need their own scope.
+    fn kind(&self) -> StmtKind {
+        StmtKind::Var
+    }
+
+    fn to_sexpr(&self) -> String {
+        let names: Vec<String> = self.names.iter().map(write_token).collect();
+        format!(
+            "(destructure ({}) {} {})",
+            names.join(" "),
+            write_token(&self.equals),
+            self.initializer.to_sexpr()
+        )
+    }
 }
 
 pub struct Block {
     pub(crate) statements: Vec<Rc<dyn Stmt>>,
+    // Only blocks declaring something need their own environment.
+    pub(crate) scoped: bool,
+}
+
+impl Block {
+    pub(crate) fn new(statements: Vec<Rc<dyn Stmt>>) -> Self {
+        let scoped = statements.iter().any(|statement| {
+            matches!(
+                statement.kind(),
+                StmtKind::Var | StmtKind::Function(_) | StmtKind::ClassStmt
+            )
+        });
+        Block { statements, scoped }
+    }
 }
 
 impl Stmt for Block {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
-        let scoped_env = Rc::new(Environment::new_child(env.clone()));
+        let scoped_env = if self.scoped {
+            Rc::new(Environment::new_child(env))
+        } else {
+            env
+        };
+        hoist(&self.statements, &scoped_env);
         for statement in &self.statements {
-            match statement.evaluate(Rc::clone(&scoped_env))? {
-                LoxValue::Return(a) => {
-                    return Ok(LoxValue::Return(a.clone()));
-                }
-                _ => {}
+            if let LoxValue::Return(a) = execute(statement, Rc::clone(&scoped_env))? {
+                return Ok(LoxValue::Return(a));
             }
         }
         Ok(LoxValue::None)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        self.statements
+            .iter()
+            .map(|statement| Node::Stmt(statement.as_ref()))
+            .collect()
+    }
+
+    fn line(&self) -> u64 {
+        self.statements
+            .first()
+            .map_or(0, |statement| statement.line())
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::Block
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(block {})", write_stmts(&self.statements))
+    }
 }
 
 pub struct If {
     pub(crate) condition: Rc<dyn Expr>,
     pub(crate) then_branch: Rc<dyn Stmt>,
     pub(crate) else_branch: Option<Rc<dyn Stmt>>,
+    pub(crate) line: u64,
 }
 
 impl Stmt for If {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
         match is_truthy(self.condition.evaluate(Rc::clone(&env))?, false)? {
-            LoxValue::Bool(true) => self.then_branch.evaluate(Rc::clone(&env)),
+            LoxValue::Bool(true) => execute(&self.then_branch, Rc::clone(&env)),
             _ => match &self.else_branch {
                 None => Ok(LoxValue::None),
-                Some(a) => a.evaluate(Rc::clone(&env)),
+                Some(a) => execute(a, Rc::clone(&env)),
             },
         }
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        let mut children = vec![
+            Node::Expr(self.condition.as_ref()),
+            Node::Stmt(self.then_branch.as_ref()),
+        ];
+        children.extend(
+            self.else_branch
+                .iter()
+                .map(|branch| Node::Stmt(branch.as_ref())),
+        );
+        children
+    }
+
+    fn line(&self) -> u64 {
+        self.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::If
     }
+
+    fn to_sexpr(&self) -> String {
+        match &self.else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {} {})",
+                self.line,
+                self.condition.to_sexpr(),
+                self.then_branch.to_sexpr(),
+                else_branch.to_sexpr()
+            ),
+            None => format!(
+                "(if {} {} {})",
+                self.line,
+                self.condition.to_sexpr(),
+                self.then_branch.to_sexpr()
+            ),
+        }
+    }
 }
 
 pub struct While {
     pub(crate) condition: Rc<dyn Expr>,
     pub(crate) body: Rc<dyn Stmt>,
+    pub(crate) line: u64,
 }
 
 impl Stmt for While {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
         while is_truthy(self.condition.evaluate(Rc::clone(&env))?, false)? == LoxValue::Bool(true) {
-            match self.body.evaluate(Rc::clone(&env))? {
+            match execute(&self.body, Rc::clone(&env))? {
                 LoxValue::Return(a) => {
                     return Ok(LoxValue::Return(a.clone()));
                 }
@@ -140,25 +407,58 @@ impl Stmt for While {
         Ok(LoxValue::None)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![
+            Node::Expr(self.condition.as_ref()),
+            Node::Stmt(self.body.as_ref()),
+        ]
+    }
+
+    fn line(&self) -> u64 {
+        self.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::While
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(while {} {} {})",
+            self.line,
+            self.condition.to_sexpr(),
+            self.body.to_sexpr()
+        )
+    }
 }
 
 pub struct Function {
     pub(crate) name: Token,
     pub(crate) params: Vec<Token>,
     pub(crate) body: Vec<Rc<dyn Stmt>>,
+    // `fluent` methods give back `this` when they don't return anything else, for chaining.
+    pub(crate) fluent: bool,
 }
 
-impl Stmt for Function {
-    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+impl Function {
+    // Closes over a snapshot of `env`, also used by accessors and lambdas which don't define a
+    // name.
+    pub(crate) fn callable(&self, env: &Rc<Environment>, string: String) -> Rc<Callable> {
         let borrow: &Environment = env.borrow();
         let env_clone = Rc::new(borrow.clone());
+        // A method's environment is shared by all its calls and binding gives it the next
+        // instance, so a closure made inside a block of the method keeps the current `this`.
+        if !env_clone.values.borrow().contains_key("this") {
+            if let Ok(this) = env.get_by_string(String::from("this")) {
+                env_clone.define(String::from("this"), this);
+            }
+        }
         let cloned_body = self.body.clone();
         let cloned_params = self.params.clone();
-        let function = LoxValue::Function(Rc::new(Callable {
+        let fluent = self.fluent;
+        Rc::new(Callable {
             arity: self.params.len(),
+            variadic: false,
             function: Rc::new(move |arguments, environment| {
                 for (i, parameter) in cloned_params.iter().enumerate() {
                     environment.define(
@@ -167,46 +467,108 @@ impl Stmt for Function {
                     );
                 }
                 let mut interpreter = Interpreter::new_with_env(Rc::clone(&environment));
-                interpreter.interpret(cloned_body.clone())
+                match interpreter.execute(cloned_body.clone())? {
+                    LoxValue::None if fluent => environment
+                        .get_by_string(String::from("this"))
+                        .or(Ok(LoxValue::None)),
+                    value => Ok(value),
+                }
             }),
-            string: format!("<fn {}>", self.name.lexeme),
+            string,
             name: self.name.clone(),
             environment: Rc::clone(&env_clone),
             is_initializer: RefCell::new(false),
-        }));
+        })
+    }
+}
+
+impl Stmt for Function {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        let function =
+            LoxValue::Function(self.callable(&env, format!("<fn {}>", self.name.lexeme)));
+        // The closure is a snapshot, so functions declared earlier in this scope get this one
+        // added to theirs. Otherwise they couldn't call each other.
+        for value in env.values.borrow().values() {
+            if let LoxValue::Function(sibling) = value {
+                if sibling.declared_in(&env) {
+                    sibling
+                        .environment
+                        .define(self.name.lexeme.clone(), function.clone());
+                }
+            }
+        }
         env.define(self.name.lexeme.clone(), function.clone());
         Ok(function)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        self.body
+            .iter()
+            .map(|statement| Node::Stmt(statement.as_ref()))
+            .collect()
+    }
+
+    fn line(&self) -> u64 {
+        self.name.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::Function(Function {
             name: self.name.clone(),
             params: self.params.clone(),
             body: self.body.clone(),
+            fluent: self.fluent,
         })
     }
+
+    fn to_sexpr(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(write_token).collect();
+        format!(
+            "(function {} {} ({}) {})",
+            write_token(&self.name),
+            self.fluent,
+            params.join(" "),
+            write_stmts(&self.body)
+        )
+    }
 }
 
 pub struct ReturnStmt {
     pub(crate) value: Rc<dyn Expr>,
+    pub(crate) line: u64,
 }
 
 impl Stmt for ReturnStmt {
     fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
-        match self.value.kind() {
-            Kind::NoOp => Ok(LoxValue::Return(Box::new(LoxValue::None))),
-            _ => Ok(LoxValue::Return(Box::new(self.value.evaluate(env)?))),
-        }
+        Ok(LoxValue::Return(Box::new(self.value.evaluate(env)?)))
+    }
+
+    fn children(&self) -> Vec<Node<'_>> {
+        vec![Node::Expr(self.value.as_ref())]
+    }
+
+    fn line(&self) -> u64 {
+        self.line
     }
 
     fn kind(&self) -> StmtKind {
         StmtKind::ReturnStmt
     }
+
+    fn to_sexpr(&self) -> String {
+        format!("(return {} {})", self.line, self.value.to_sexpr())
+    }
 }
 
 pub struct ClassStmt {
     pub(crate) name: Token,
     pub(crate) methods: Vec<Rc<dyn Stmt>>,
+    pub(crate) getters: Vec<Rc<dyn Stmt>>,
+    pub(crate) setters: Vec<Rc<dyn Stmt>>,
+    // `static name() { ... }`, called on the class with `this` as the class.
+    pub(crate) static_methods: Vec<Rc<dyn Stmt>>,
+    // `static { ... }` blocks, run in order once the class is defined with `this` as the class.
+    pub(crate) static_blocks: Vec<Rc<dyn Stmt>>,
     pub(crate) super_class: Option<Rc<dyn Expr>>,
 }
 
@@ -216,18 +578,15 @@ impl Stmt for ClassStmt {
         match &self.super_class {
             None => {}
             Some(a) => {
-                match a.kind() {
-                    Kind::Variable(super_class) => {
-                        if super_class.lexeme == self.name.lexeme {
-                            return Err((
-                                String::from("A class can't inherit from itself."),
-                                super_class,
-                            ));
-                        }
-                    }
-                    _ => {
-                        //would be weird
+                if let Kind::Variable(super_class) = a.kind() {
+                    if super_class.lexeme == self.name.lexeme {
+                        return Err((
+                            String::from("A class can't inherit from itself."),
+                            super_class,
+                        ));
                     }
+                } else {
+                    //would be weird
                 }
 
                 match a.evaluate(Rc::clone(&env))? {
@@ -245,46 +604,114 @@ impl Stmt for ClassStmt {
         }
 
         let mut methods: HashMap<String, LoxValue> = HashMap::new();
-        for method in &self.methods {
-            match method.kind() {
-                StmtKind::Function(function) => {
-                    let thing = function.evaluate(Rc::clone(&env))?;
-                    match thing {
-                        LoxValue::Function(callable) => {
-                            if callable.name.lexeme == "init" {
-                                callable.set_initializer();
-                            }
-                            match possible_super_class {
-                                None => {}
-                                Some(ref a) => {
-                                    callable.bind_super(LoxValue::Class(Rc::clone(&a)));
-                                }
-                            }
-
-                            methods.insert(
-                                function.name.lexeme.clone(),
-                                LoxValue::Function(Rc::clone(&callable)),
-                            );
-                        }
-                        _ => {
-                            methods.insert(function.name.lexeme.clone(), thing.clone());
-                        }
-                    }
-                }
-                _ => {}
+        for (name, callable) in class_functions(&self.methods, &env, &possible_super_class) {
+            if name == "init" {
+                callable.set_initializer();
             }
+            methods.insert(name, LoxValue::Function(callable));
         }
-        let class = LoxValue::Class(Rc::new(Class {
+        let class = Rc::new(Class {
             arity: 0,
             name: self.name.lexeme.clone(),
             methods: RefCell::new(methods),
+            getters: class_functions(&self.getters, &env, &possible_super_class),
+            setters: class_functions(&self.setters, &env, &possible_super_class),
+            statics: RefCell::new(HashMap::new()),
+            static_methods: class_functions(&self.static_methods, &env, &None),
             super_class: possible_super_class,
-        }));
-        env.define(self.name.lexeme.clone(), class);
+            runtime: Rc::clone(&env.runtime),
+        });
+        env.define(self.name.lexeme.clone(), LoxValue::Class(Rc::clone(&class)));
+        if !self.static_blocks.is_empty() {
+            let static_env = Rc::new(Environment::new_child(Rc::clone(&env)));
+            static_env.define(String::from("this"), LoxValue::Class(Rc::clone(&class)));
+            for block in &self.static_blocks {
+                execute(block, Rc::clone(&static_env))?;
+            }
+        }
         Ok(LoxValue::None)
     }
 
+    fn children(&self) -> Vec<Node<'_>> {
+        let mut children: Vec<Node> = self
+            .super_class
+            .iter()
+            .map(|class| Node::Expr(class.as_ref()))
+            .collect();
+        children.extend(
+            self.methods
+                .iter()
+                .chain(&self.getters)
+                .chain(&self.setters)
+                .chain(&self.static_methods)
+                .chain(&self.static_blocks)
+                .map(|method| Node::Stmt(method.as_ref())),
+        );
+        children
+    }
+
+    fn line(&self) -> u64 {
+        self.name.line
+    }
+
     fn kind(&self) -> StmtKind {
         StmtKind::ClassStmt
     }
+
+    fn to_sexpr(&self) -> String {
+        format!(
+            "(class {} {} ({}) ({}) ({}) ({}) ({}))",
+            write_token(&self.name),
+            self.super_class
+                .as_ref()
+                .map_or_else(|| String::from("nil"), |class| class.to_sexpr()),
+            write_stmts(&self.methods),
+            write_stmts(&self.getters),
+            write_stmts(&self.setters),
+            write_stmts(&self.static_methods),
+            write_stmts(&self.static_blocks)
+        )
+    }
+}
+
+fn class_functions(
+    functions: &[Rc<dyn Stmt>],
+    env: &Rc<Environment>,
+    super_class: &Option<Rc<Class>>,
+) -> HashMap<String, Rc<Callable>> {
+    let mut callables = HashMap::new();
+    // Not evaluated like a declaration, that would define the function's name in `env`.
+    for function in functions {
+        if let StmtKind::Function(function) = function.kind() {
+            let callable = function.callable(env, format!("<fn {}>", function.name.lexeme));
+            if let Some(class) = super_class {
+                callable.bind_super(LoxValue::Class(Rc::clone(class)));
+            }
+            callables.insert(function.name.lexeme.clone(), callable);
+        }
+    }
+    callables
+}
+
+pub struct ImportStmt {
+    pub(crate) path: Token,
+}
+
+impl Stmt for ImportStmt {
+    fn evaluate(&self, env: Rc<Environment>) -> Result<LoxValue, (String, Token)> {
+        module::import(&self.path, &env)?;
+        Ok(LoxValue::None)
+    }
+
+    fn line(&self) -> u64 {
+        self.path.line
+    }
+
+    fn kind(&self) -> StmtKind {
+        StmtKind::Import
+    }
+
+    fn to_sexpr(&self) -> String {
+        format!("(import {})", write_token(&self.path))
+    }
 }
@@ -1,6 +1,7 @@
 use crate::loxvalue::LoxValue;
 use crate::tokentype::TokenType;
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Token {
@@ -8,6 +9,25 @@ pub struct Token {
     pub(crate) lexeme: String,
     pub(crate) literal: LoxValue,
     pub(crate) line: u64,
+    // In characters from the start of its line, None for tokens that aren't from the source or
+    // span lines.
+    pub(crate) column: Option<usize>,
+    // The text it was scanned from, an error shows the line from there. None like `column`.
+    pub(crate) source: Option<Rc<str>>,
+}
+
+impl Token {
+    // Not from the source, line 0 tells the call site to report errors at the call instead.
+    pub(crate) fn synthetic(lexeme: &str) -> Token {
+        Token {
+            token_type: TokenType::Identifier,
+            lexeme: String::from(lexeme),
+            literal: LoxValue::None,
+            line: 0,
+            column: None,
+            source: None,
+        }
+    }
 }
 
 impl fmt::Debug for Token {
@@ -6,12 +6,14 @@ pub enum TokenType {
     LeftBrace,
     RightBrace,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
     SemiColon,
     Slash,
     Star,
+    Backslash,
     // One or two character tokens
     Bang,
     BangEqual,
@@ -21,6 +23,9 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionDot,
+    HashLeftBrace,
+    Arrow,
     // Literals
     Identifier,
     String,
@@ -28,11 +33,14 @@ pub enum TokenType {
     //Keywords
     And,
     Class,
+    Const,
     Else,
     False,
     Fun,
     For,
     If,
+    Import,
+    In,
     Nil,
     Or,
     Print,
@@ -43,5 +51,6 @@ pub enum TokenType {
     Var,
     While,
 
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
@@ -0,0 +1,49 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+
+// A child of a node, statements like `if` hold both expressions and statements.
+pub enum Node<'a> {
+    Expr(&'a dyn Expr),
+    Stmt(&'a dyn Stmt),
+}
+
+// For passes over the tree, a linter or a pretty printer. Override the methods for the nodes of
+// interest and call `walk_expr`/`walk_stmt` from them to keep going into the children.
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &dyn Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_stmt(&mut self, stmt: &dyn Stmt) {
+        walk_stmt(self, stmt);
+    }
+}
+
+impl dyn Expr {
+    pub fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_expr(self);
+    }
+}
+
+impl dyn Stmt {
+    pub fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_stmt(self);
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &dyn Expr) {
+    walk(visitor, expr.children());
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &dyn Stmt) {
+    walk(visitor, stmt.children());
+}
+
+fn walk<V: Visitor + ?Sized>(visitor: &mut V, children: Vec<Node>) {
+    for child in children {
+        match child {
+            Node::Expr(expr) => visitor.visit_expr(expr),
+            Node::Stmt(stmt) => visitor.visit_stmt(stmt),
+        }
+    }
+}
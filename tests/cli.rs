@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs the rilox binary from the crate root, giving its exit code, stdout and stderr.
+fn rilox(args: &[&str]) -> (i32, String, String) {
+    rilox_with_input(args, "")
+}
+
+fn rilox_with_input(args: &[&str], input: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rilox"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // A script doesn't read its input, it may be gone before the input is written.
+    if !input.is_empty() {
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+    }
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn script_path_is_the_path_given_to_run() {
+    let (code, stdout, _) = rilox(&["tests/scripts/script_path.lox"]);
+    assert_eq!(code, 0);
+    assert_eq!(
+        stdout,
+        "\"tests/scripts/script_path.lox\"\n\"tests/scripts\"\n"
+    );
+}
+
+#[test]
+fn repl_keeps_the_last_expression_value_in_underscore() {
+    let input = "2 + 3;\n_ * 2;\nvar y = 1;\nprint _;\n";
+    let (code, stdout, _) = rilox_with_input(&[], input);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "> > > > 10\n> ");
+}
+
+#[test]
+fn cli_prints_diagnostics_to_stderr() {
+    let (code, stdout, stderr) = rilox_with_input(&[], "print nil.x;\n");
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "> > ");
+    assert_eq!(
+        stderr,
+        "Only instances have properties.\n[line 1]\n    print nil.x;\n              ^\n"
+    );
+}
+
+#[test]
+fn closed_stdout_is_a_runtime_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rilox"))
+        .arg("tests/scripts/print_many.lox")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr.starts_with("Could not write output: "), "{}", stderr);
+    assert!(!stderr.contains("Internal error"));
+}
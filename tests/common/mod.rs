@@ -0,0 +1,54 @@
+// Shared by the test files, not every file uses every helper.
+#![allow(dead_code)]
+
+use rilox::diagnostic::{Diagnostic, DiagnosticKind};
+use rilox::lox::run_capturing;
+use std::fs;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SCRIPTS: AtomicUsize = AtomicUsize::new(0);
+
+// Runs the source as a script with the rilox binary and the given flags. The script is written to
+// the crate root so imports resolve against it like against the working directory.
+pub fn run(flags: &[&str], source: &str) -> Output {
+    let path = format!(
+        "{}/.test-{}-{}.lox",
+        env!("CARGO_MANIFEST_DIR"),
+        std::process::id(),
+        SCRIPTS.fetch_add(1, Ordering::Relaxed)
+    );
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rilox"))
+        .args(flags)
+        .arg(&path)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output();
+    fs::remove_file(&path).unwrap();
+    output.unwrap()
+}
+
+// What the program printed, failing the test on any error.
+pub fn output(source: &str) -> String {
+    let (_, output, diagnostics) = run_capturing(source);
+    let errors: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.kind != DiagnosticKind::Warning)
+        .collect();
+    assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+    output
+}
+
+// The message of the first error, failing the test when the program ran without one.
+pub fn error(source: &str) -> String {
+    let (_, _, diagnostics) = run_capturing(source);
+    first_error(&diagnostics)
+}
+
+pub fn first_error(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.kind != DiagnosticKind::Warning)
+        .map(|diagnostic| diagnostic.message.clone())
+        .expect("expected an error")
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::{error, run};
+use rilox::diagnostic::{Diagnostic, DiagnosticKind};
+use rilox::lox::Lox;
+
+#[test]
+fn runtime_errors_show_the_source_line_with_the_token_underlined() {
+    let output = run(&[], "var a = nil;\nprint a.x;");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Only instances have properties.\n[line 2]\n    print a.x;\n            ^\n"
+    );
+}
+
+#[test]
+fn runtime_errors_in_an_imported_module_show_the_line_of_the_module() {
+    let output = run(&[], "import \"tests/modules/failing.lox\";\n\nfail();");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Only instances have properties.\n[line 3]\n      return a.x;\n               ^\n"
+    );
+}
+
+#[test]
+fn wrong_argument_counts_of_known_functions_are_reported_before_running() {
+    let output = run(&[], "print 1;\nfun f(a, b) {}\nf(1);");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 3] Error at 'f': Expected 2 argument(s) but got 1.\n"
+    );
+}
+
+#[test]
+fn wrong_argument_counts_of_known_classes_are_reported_before_running() {
+    assert_eq!(
+        error("class A { init(x) {} }\nA();"),
+        "Expected 1 argument(s) but got 0."
+    );
+}
+
+#[test]
+fn indirect_calls_are_checked_when_they_run() {
+    let output = run(&[], "fun f(a, b) {}\nvar g = f;\nprint 1;\ng(1);");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Expected 2 argument(s) but got 1.\n[line 4]\n"));
+}
+
+// The warnings of running the source with the lint on.
+fn lint(source: &str) -> Vec<String> {
+    let output = run(&["--lint"], source);
+    String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains("] Warning "))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn lint_warns_about_operations_on_literals_that_always_fail() {
+    let warnings = lint("print 1 + \"a\";");
+    assert_eq!(
+        warnings,
+        ["[line 1] Warning at '+': Can only add two numbers or concatenate two strings."]
+    );
+    assert_eq!(
+        lint("print 1();"),
+        ["[line 1] Warning at ')': Can only call functions and classes."]
+    );
+    assert_eq!(
+        lint("print (2).x;"),
+        ["[line 1] Warning at 'x': Only instances have properties."]
+    );
+}
+
+#[test]
+fn lint_leaves_unknown_types_alone() {
+    assert!(lint("var a = 1; var b = \"a\"; print a + b;").is_empty());
+    assert!(lint("print 1 + 2; print \"a\" + \"b\";").is_empty());
+}
+
+#[test]
+fn shadowing_a_native_warns() {
+    assert_eq!(
+        lint("fun clock() { return 0; } print clock();"),
+        ["[line 1] Warning at 'clock': Declaration shadows the native 'clock'."]
+    );
+    assert!(lint("{ var clock = 1; } fun f(clock) {}").is_empty());
+}
+
+#[test]
+fn shadowing_a_native_is_an_error_in_strict_mode() {
+    let output = run(&["--strict"], "fun clock() {}");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 1] Error at 'clock': Can't redefine the native 'clock' in strict mode.\n"
+    );
+}
+
+#[test]
+fn assignment_as_a_condition_warns_unless_parenthesized() {
+    let warning = "[line 1] Warning at 'x': Assignment used as a condition; did you mean '=='?";
+    assert_eq!(lint("var x; if (x = 5) print x;"), [warning]);
+    assert_eq!(lint("var x; while (x = false) {}"), [warning]);
+    assert!(lint("var x; if ((x = 5)) print x;").is_empty());
+}
+
+fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    Lox::new().run(String::from(source), true)
+}
+
+#[test]
+fn run_returns_errors_and_warnings_with_their_kinds() {
+    let mut lox = Lox::new();
+    lox.set_lint(true);
+    let diagnostics = lox.run(String::from("var x;\nif (x = 5) print x;\nprint ;"), true);
+    let kinds: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.kind)
+        .collect();
+    assert_eq!(kinds, [DiagnosticKind::Error, DiagnosticKind::Warning]);
+    assert_eq!(diagnostics[0].message, "Expect expression.");
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, Some(7)));
+    assert_eq!((diagnostics[1].line, diagnostics[1].column), (2, Some(5)));
+}
+
+#[test]
+fn columns_point_at_the_token_even_when_its_lexeme_comes_earlier_on_the_line() {
+    let diagnostics = diagnostics("var x = nil;\nprint x.x;");
+    assert_eq!(diagnostics[0].column, Some(9));
+    assert_eq!(
+        diagnostics[0].context.as_deref(),
+        Some("    print x.x;\n            ^")
+    );
+}
+
+#[test]
+fn columns_after_a_string_spanning_lines_count_from_its_last_line() {
+    let diagnostics = diagnostics("print \"a\nb\" + 1;");
+    assert_eq!(diagnostics[0].line, 2);
+    assert_eq!(diagnostics[0].column, Some(4));
+    assert_eq!(
+        diagnostics[0].context.as_deref(),
+        Some("    b\" + 1;\n       ^")
+    );
+}
+
+#[test]
+fn runtime_errors_in_evaluated_code_show_the_line_of_that_code() {
+    let mut lox = Lox::new();
+    lox.set_allow_eval(true);
+    let diagnostics = lox.run(
+        String::from("eval(\"fun g() {\n  return nil.x;\n}\");\ng();"),
+        true,
+    );
+    assert_eq!(diagnostics[0].line, 2);
+    assert_eq!(
+        diagnostics[0].context.as_deref(),
+        Some("      return nil.x;\n                 ^")
+    );
+}
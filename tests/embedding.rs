@@ -0,0 +1,472 @@
+mod common;
+
+use common::{first_error, output, run};
+use rilox::interpreter::Interpreter;
+use rilox::lox::{run_capturing, Lox};
+use rilox::loxvalue::{LoxValue, NumberFormat};
+use rilox::parser::Parser;
+use rilox::runtime::{Input, Trace};
+use rilox::scanner::Scanner;
+use rilox::sexpr;
+use rilox::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+fn parse(source: &str) -> Vec<Rc<dyn Stmt>> {
+    let tokens = Scanner::new(String::from(source)).scan_tokens().unwrap();
+    let (statements, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    statements
+}
+
+fn interpret(interpreter: &mut Interpreter, source: &str) {
+    assert!(interpreter.interpret(parse(source)).is_ok());
+}
+
+#[test]
+fn reset_forgets_globals_from_earlier_runs() {
+    let mut interpreter = Interpreter::new();
+    interpret(&mut interpreter, "var x = 1;");
+    interpret(&mut interpreter, "x;");
+    interpreter.reset();
+    let (message, _) = interpreter.interpret(parse("x;")).unwrap_err();
+    assert_eq!(message, "Undefined variable 'x'.");
+}
+
+#[test]
+fn reset_keeps_the_natives() {
+    let mut interpreter = Interpreter::new();
+    interpret(&mut interpreter, "var clock = nil;");
+    interpreter.reset();
+    interpret(&mut interpreter, "clock();");
+}
+
+#[test]
+fn allocation_limit_stops_constructing_instances() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allocation_limit(Some(10));
+    let source = "class Node {} var head = nil; \
+                  for (var i = 0; i < 100; i = i + 1) { var node = Node(); node.next = head; head = node; }";
+    let (message, _) = interpreter.interpret(parse(source)).unwrap_err();
+    assert_eq!(message, "Allocation limit exceeded.");
+}
+
+#[test]
+fn allocation_limit_counts_only_live_instances() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allocation_limit(Some(10));
+    interpret(
+        &mut interpreter,
+        "class Node {} for (var i = 0; i < 100; i = i + 1) { Node(); }",
+    );
+}
+
+#[test]
+fn dump_state_shows_variables_and_instance_fields() {
+    let mut interpreter = Interpreter::new();
+    interpret(
+        &mut interpreter,
+        "var answer = 42; var greeting = \"hi\"; class Point {} var p = Point(); p.x = 3;",
+    );
+    let dump = interpreter.dump_state();
+    assert!(dump.contains("answer = 42"));
+    assert!(dump.contains("greeting = \"hi\""));
+    assert!(dump.contains("p = Point instance"));
+    assert!(dump.contains("\n    x = 3\n"));
+}
+
+#[test]
+fn max_depth_can_be_lowered() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_depth(Some(10));
+    interpret(
+        &mut interpreter,
+        "fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); } f(4);",
+    );
+    let (message, _) = interpreter.interpret(parse("f(5);")).unwrap_err();
+    assert_eq!(message, "Maximum evaluation depth exceeded.");
+}
+
+#[test]
+fn max_nesting_can_be_lowered() {
+    let nested = |source: &str| {
+        let tokens = Scanner::new(String::from(source)).scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.set_max_nesting(3);
+        parser.parse().1
+    };
+    assert!(nested("print ((1));").is_empty());
+    let errors = nested("print ((((1))));");
+    assert_eq!(errors[0].1, "Expression nesting too deep.");
+}
+
+#[test]
+fn strict_math_rejects_nan_and_infinity() {
+    let strict_math = |source| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_strict_math(true);
+        interpreter.interpret(parse(source)).unwrap_err().0
+    };
+    assert_eq!(strict_math("0 / 0;"), "Arithmetic produced NaN.");
+    assert_eq!(strict_math("1 / 0;"), "Arithmetic produced infinity.");
+    assert_eq!(strict_math("1e308 * 10;"), "Arithmetic produced infinity.");
+    assert_eq!(
+        strict_math("-1e308 - 1e308;"),
+        "Arithmetic produced infinity."
+    );
+}
+
+#[test]
+fn math_follows_ieee_without_strict_math() {
+    assert_eq!(
+        output("print 0 / 0; print 1 / 0; print 1e308 * 10;"),
+        "NaN\ninf\ninf\n"
+    );
+}
+
+#[test]
+fn sleep_needs_the_host_to_allow_it() {
+    let mut interpreter = Interpreter::new();
+    let (msg, _) = interpreter.interpret(parse("sleep(0);")).err().unwrap();
+    assert_eq!(msg, "Sleeping is disabled.");
+}
+
+#[test]
+fn sleep_returns_for_zero_and_rejects_negative_seconds() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_sleep(true);
+    let start = Instant::now();
+    interpret(&mut interpreter, "sleep(0); sleep(0.001);");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    let (msg, _) = interpreter
+        .interpret(parse("sleep(-0.001);"))
+        .err()
+        .unwrap();
+    assert_eq!(msg, "Seconds must be a non-negative number.");
+}
+
+#[test]
+fn sleep_rejects_durations_too_large_to_represent() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_sleep(true);
+    let (msg, _) = interpreter.interpret(parse("sleep(1e20);")).err().unwrap();
+    assert_eq!(msg, "Seconds are too large.");
+}
+
+#[test]
+fn batch_mode_reports_every_error_and_keeps_going() {
+    let source = "var a = nil;\nprint a.x;\nvar b = 1;\nprint -\"b\";\nprint b;";
+    let output = run(&["--keep-going"], source);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("[line "))
+        .collect();
+    assert_eq!(lines, ["[line 2]", "[line 4]"]);
+}
+
+#[test]
+fn interpret_all_runs_the_statements_after_a_failing_one() {
+    let mut interpreter = Interpreter::new();
+    let tokens = Scanner::new(String::from("var a = nil; a.x; a.y; var after = true;"))
+        .scan_tokens()
+        .unwrap();
+    let (statements, _) = Parser::new(tokens).parse();
+    let errors = interpreter.interpret_all(statements);
+    assert_eq!(errors.len(), 2);
+    assert!(interpreter.dump_state().contains("after = true"));
+}
+
+#[test]
+fn host_globals_can_be_read_and_reassigned() {
+    let globals = HashMap::from([(
+        String::from("version"),
+        LoxValue::String(String::from("1.2")),
+    )]);
+    let mut interpreter = Interpreter::with_globals(globals);
+    interpret(&mut interpreter, "var seen = version; version = \"2.0\";");
+    let dump = interpreter.dump_state();
+    assert!(dump.contains("seen = \"1.2\""));
+    assert!(dump.contains("version = \"2.0\""));
+}
+
+#[test]
+fn reset_defines_the_host_globals_again() {
+    let globals = HashMap::from([(String::from("limit"), LoxValue::Int(3))]);
+    let mut interpreter = Interpreter::with_globals(globals);
+    interpret(&mut interpreter, "limit = 5; var mine = 1;");
+    interpreter.reset();
+    let dump = interpreter.dump_state();
+    assert!(dump.contains("limit = 3"));
+    assert!(!dump.contains("mine"));
+}
+
+#[test]
+fn eval_runs_source_in_the_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_eval(true);
+    interpret(
+        &mut interpreter,
+        "var three = eval(\"1 + 2\"); fun f() { eval(\"var g = 9;\"); } f(); var seen = g;",
+    );
+    let dump = interpreter.dump_state();
+    assert!(dump.contains("three = 3"));
+    assert!(dump.contains("seen = 9"));
+}
+
+#[test]
+fn constants_stay_constant_across_runs_and_eval() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_eval(true);
+    interpret(&mut interpreter, "const X = 1; lazy const Y = 2;");
+    let (msg, _) = interpreter.interpret(parse("X = 2;")).unwrap_err();
+    assert_eq!(msg, "Can't assign to the constant 'X'.");
+    let (msg, _) = interpreter.interpret(parse("Y = 3;")).unwrap_err();
+    assert_eq!(msg, "Can't assign to the constant 'Y'.");
+    let (msg, _) = interpreter
+        .interpret(parse("eval(\"X = 2;\");"))
+        .unwrap_err();
+    assert_eq!(msg, "Can't assign to the constant 'X'. [line 1 in eval]");
+    interpret(&mut interpreter, "var X = 5; X = 6;");
+    assert!(interpreter.dump_state().contains("X = 6"));
+}
+
+#[test]
+fn breakpoint_runs_lines_from_its_input_in_the_calling_scope() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_breakpoint(true);
+    interpreter.set_breakpoint_input(Some(Input::new(Cursor::new(
+        "y;\ny = y + 1;\nnope;\ncontinue\nprint 0;\n",
+    ))));
+    interpreter.capture_output();
+    interpret(
+        &mut interpreter,
+        "fun f(x) { var y = x * 2; breakpoint(); print y; } f(5);",
+    );
+    assert_eq!(
+        interpreter.take_output(),
+        "debug> 10\ndebug> 11\ndebug> Undefined variable 'nope'. [line 1 in eval]\ndebug> 11\n"
+    );
+}
+
+#[test]
+fn the_cli_allows_eval_only_with_its_flag() {
+    let source = "print eval(\"1 + 2\");";
+    let output = run(&[], source);
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Eval is disabled."));
+    let output = run(&["--allow-eval"], source);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn eval_is_disabled_by_default() {
+    let mut interpreter = Interpreter::new();
+    let (msg, _) = interpreter.interpret(parse("eval(\"1\");")).err().unwrap();
+    assert_eq!(msg, "Eval is disabled.");
+}
+
+#[test]
+fn trace_fires_once_per_statement_with_its_line() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let record = Rc::clone(&seen);
+    let mut lox = Lox::new();
+    lox.set_trace(Some(Trace::new(move |line, description| {
+        record
+            .borrow_mut()
+            .push(format!("{} {}", line, description))
+    })));
+    assert!(lox
+        .run(
+            String::from("var a = 1;\nif (a > 0)\n  a = 2;\nprint a;"),
+            true
+        )
+        .is_empty());
+    assert_eq!(*seen.borrow(), ["1 var", "2 if", "3 expression", "4 print"]);
+}
+
+#[test]
+fn panics_become_errors_on_the_line_of_their_statement() {
+    let mut lox = Lox::new();
+    lox.set_trace(Some(Trace::new(|line, _| {
+        if line == 3 {
+            panic!("boom")
+        }
+    })));
+    let diagnostics = lox.run(String::from("var a = 1;\nvar b = 2;\nprint a + b;"), true);
+    assert_eq!(first_error(&diagnostics), "Internal error: boom.");
+    assert_eq!(diagnostics[0].line, 3);
+}
+
+#[test]
+fn interpret_returns_a_panic_as_an_err() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_trace(Some(Trace::new(|_, _| panic!("boom"))));
+    let (message, _) = interpreter.interpret(parse("print 1;")).unwrap_err();
+    assert_eq!(message, "Internal error: boom.");
+}
+
+#[test]
+fn trace_sees_the_statements_of_eval() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let record = Rc::clone(&seen);
+    let mut lox = Lox::new();
+    lox.set_allow_eval(true);
+    lox.set_trace(Some(Trace::new(move |line, description| {
+        record
+            .borrow_mut()
+            .push(format!("{} {}", line, description))
+    })));
+    assert!(lox
+        .run(String::from("eval(\"var a = 1; print a;\");"), true)
+        .is_empty());
+    assert_eq!(*seen.borrow(), ["1 expression", "1 var", "1 print"]);
+}
+
+#[test]
+fn eval_hoists_functions_like_a_script() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_allow_eval(true);
+    interpret(
+        &mut interpreter,
+        "var answer = eval(\"var r = twice(21); fun twice(n) { return n * 2; } r;\");",
+    );
+    assert!(interpreter.dump_state().contains("answer = 42"));
+}
+
+#[test]
+fn profiling_counts_calls_per_function() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_profiling(true);
+    interpret(
+        &mut interpreter,
+        "fun f() {} fun g() {} for (var i = 0; i < 5; i = i + 1) f(); g(); \
+         var report = profile_report(); var f_calls = report.f.calls; var g_calls = report.g.calls; \
+         var timed = report.f.total_time >= 0;",
+    );
+    let dump = interpreter.dump_state();
+    assert!(dump.contains("f_calls = 5"));
+    assert!(dump.contains("g_calls = 1"));
+    assert!(dump.contains("timed = true"));
+}
+
+#[test]
+fn profiling_is_off_by_default() {
+    let diagnostics = Lox::new().run(String::from("profile_report();"), true);
+    assert_eq!(first_error(&diagnostics), "Profiling is disabled.");
+}
+
+#[test]
+fn run_capturing_gives_the_output_value_and_diagnostics() {
+    let source = "for (var i = 1; i <= 3; i = i + 1) print i * i;\nprint \"done\";\n40 + 2;";
+    let (value, output, diagnostics) = run_capturing(source);
+    assert_eq!(output, "1\n4\n9\n\"done\"\n");
+    assert_eq!(value, Some(LoxValue::Int(42)));
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn run_capturing_keeps_the_output_before_an_error() {
+    let (value, output, diagnostics) = run_capturing("print 1;\nprint nil.x;\nprint 2;");
+    assert_eq!(output, "1\n");
+    assert_eq!(value, None);
+    assert_eq!(first_error(&diagnostics), "Only instances have properties.");
+    assert_eq!(diagnostics[0].line, 2);
+}
+
+fn printed(interpreter: &mut Interpreter, source: &str) -> String {
+    interpreter.capture_output();
+    interpret(interpreter, source);
+    interpreter.take_output()
+}
+
+#[test]
+fn number_format_switches_to_scientific_outside_its_thresholds() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_number_format(NumberFormat {
+        significant_digits: Some(3),
+        scientific_above: Some(1e6),
+        scientific_below: Some(1e-4),
+    });
+    let source = "print 123456789.0; print 0.0000001; print 3.14159; print 0.0; print 7;";
+    assert_eq!(
+        printed(&mut interpreter, source),
+        "1.23e8\n1e-7\n3.14\n0\n7\n"
+    );
+}
+
+#[test]
+fn default_number_format_shows_the_shortest_exact_decimal() {
+    let mut interpreter = Interpreter::new();
+    let source = "print 0.0000001; print 3.14159; print 0.1 + 0.2;";
+    assert_eq!(
+        printed(&mut interpreter, source),
+        "0.0000001\n3.14159\n0.30000000000000004\n"
+    );
+}
+
+#[test]
+fn output_limit_stops_printing_once_reached() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output_limit(Some(6));
+    assert_eq!(printed(&mut interpreter, "print 12; print 34;"), "12\n34\n");
+
+    let mut lox = Lox::new();
+    lox.set_output_limit(Some(6));
+    let diagnostics = lox.run(
+        String::from("for (var i = 0; i < 1000; i = i + 1) print \"line\";"),
+        true,
+    );
+    assert_eq!(first_error(&diagnostics), "Output limit exceeded.");
+}
+
+#[test]
+fn a_program_read_back_from_its_sexpr_prints_the_same() {
+    let source = "class Shape { init(name) { this.name = name; } \
+                    get label { return \"<\" + this.name + \">\"; } } \
+                  class Square < Shape { init(side) { super.init(\"square\"); this.side = side; } \
+                    set width(value) { if (value < 0) value = 0; else this.side = value; } \
+                    fluent grow(by) { this.side = this.side + by; } \
+                    static double(n) { return n * 2; } static { this.count = 0; } } \
+                  var s = Square(2); s.grow(1).grow(0.5); s.width = -1; \
+                  print s.label; print s.side; print Square.double(2); print s?.side; \
+                  var {name} = #{ name: \"say \\x22hi\\x22 \\ bye\", other: nil }; print name; \
+                  var square = \\x -> x * x; print square(3); \
+                  for (var i = 0; i < 3 and !false; i = i + 1) print if (i == 2) \"last\" else i; \
+                  print \"e\" in \"hello\"; print 0.1 + 0.2; print -7 / 2;";
+    let statements = parse(source);
+    let text = sexpr::to_sexpr(&statements);
+    let loaded = sexpr::from_sexpr(&text).unwrap();
+    assert_eq!(sexpr::to_sexpr(&loaded), text);
+
+    let mut interpreter = Interpreter::new();
+    interpreter.capture_output();
+    assert!(interpreter.interpret(statements).is_ok());
+    let expected = interpreter.take_output();
+    let mut interpreter = Interpreter::new();
+    interpreter.capture_output();
+    assert!(interpreter.interpret(loaded).is_ok());
+    assert_eq!(interpreter.take_output(), expected);
+    assert!(expected.starts_with("\"<square>\"\n3.5\n"));
+}
+
+#[test]
+fn from_sexpr_rejects_unknown_forms() {
+    assert_eq!(
+        sexpr::from_sexpr("(frobnicate 1)").err().unwrap(),
+        "Invalid statement 'frobnicate'."
+    );
+    assert_eq!(
+        sexpr::from_sexpr("(print 1 (literal (int 1))")
+            .err()
+            .unwrap(),
+        "Expect ')' before the end."
+    );
+}
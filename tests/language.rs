@@ -0,0 +1,725 @@
+mod common;
+
+use common::{error, first_error, output, run};
+use rilox::expr::{Expr, Kind};
+use rilox::lox::{run_capturing, Lox};
+use rilox::parser::{Parser, DEFAULT_MAX_NESTING};
+use rilox::scanner::Scanner;
+use rilox::token::Token;
+use rilox::visitor::{walk_expr, Visitor};
+use std::thread;
+
+#[test]
+fn strict_mode_rejects_undeclared_variables_before_running() {
+    let result = run(&["--strict"], "print 1;\nconut = 2;");
+    assert_eq!(String::from_utf8(result.stdout).unwrap(), "");
+    assert_eq!(
+        String::from_utf8(result.stderr).unwrap(),
+        "[line 2] Error at 'conut': Undefined variable 'conut'.\n"
+    );
+}
+
+#[test]
+fn strict_mode_accepts_declared_variables() {
+    let source = "var count = 0; fun increment() { count = count + 1; } increment();";
+    let result = run(&["--strict"], source);
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8(result.stderr).unwrap(), "");
+}
+
+#[test]
+fn strict_mode_is_off_by_default() {
+    assert_eq!(output("fun f() { return y; } print 1;"), "1\n");
+}
+
+#[test]
+fn undefined_variables_suggest_a_close_name() {
+    assert_eq!(
+        error("var length = 3; fun f() { return lenght; } f();"),
+        "Undefined variable 'lenght'. Did you mean 'length'?"
+    );
+    assert_eq!(
+        error("var length = 3; print zebra;"),
+        "Undefined variable 'zebra'."
+    );
+}
+
+#[test]
+fn concatenation_in_a_loop_keeps_every_piece() {
+    let source = "var text = \"\"; for (var i = 0; i < 4; i = i + 1) { text = text + \"ab\"; } \
+                  print text;";
+    assert_eq!(output(source), "\"abababab\"\n");
+}
+
+#[test]
+fn concatenation_leaves_the_left_operand_alone() {
+    let source = "var a = \"x\"; var b = a + \"y\"; var c = a + \"z\"; print a; print b; print c;";
+    assert_eq!(output(source), "\"x\"\n\"xy\"\n\"xz\"\n");
+}
+
+#[test]
+fn loop_variables_stay_inside_the_loop() {
+    assert_eq!(
+        error("for (var i = 0; i < 3; i = i + 1) { var inner = i; } print i;"),
+        "Undefined variable 'i'."
+    );
+    assert_eq!(
+        error("for (var i = 0; i < 3; i = i + 1) { var inner = i; } print inner;"),
+        "Undefined variable 'inner'."
+    );
+}
+
+#[test]
+fn blocks_without_declarations_still_assign_outer_variables() {
+    let source = "var sum = 0; for (var i = 0; i < 5; i = i + 1) { sum = sum + i; } print sum;";
+    assert_eq!(output(source), "10\n");
+}
+
+#[test]
+fn optional_chaining_on_nil_gives_nil() {
+    assert_eq!(output("var a = nil; print a?.x;"), "nil\n");
+}
+
+#[test]
+fn optional_chaining_gives_the_field_when_present() {
+    let source = "class Point {} var p = Point(); p.x = 3; print p?.x;";
+    assert_eq!(output(source), "3\n");
+}
+
+#[test]
+fn optional_chaining_stops_at_the_first_nil_link() {
+    let source = "class Node {} var a = Node(); a.b = nil; print a?.b?.c;";
+    assert_eq!(output(source), "nil\n");
+}
+
+#[test]
+fn plain_property_access_on_nil_still_errors() {
+    assert_eq!(
+        error("var a = nil; print a.x;"),
+        "Only instances have properties."
+    );
+}
+
+#[test]
+fn missing_expressions_are_parse_errors() {
+    assert_eq!(error("print ;"), "Expect expression.");
+    assert_eq!(error("var x = ;"), "Expect expression.");
+}
+
+#[test]
+fn an_empty_script_runs() {
+    assert_eq!(output(""), "");
+}
+
+#[test]
+fn input_ending_mid_statement_is_a_parse_error() {
+    assert_eq!(error("var x = (1 +"), "Expect expression.");
+    assert_eq!(error("class A { f() {"), "Expect '}' after block.");
+}
+
+#[test]
+fn the_parser_takes_an_empty_token_stream() {
+    let (statements, errors) = Parser::new(vec![]).parse();
+    assert!(statements.is_empty());
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn the_parser_takes_a_token_stream_without_eof() {
+    let without_eof = |source: &str| {
+        let mut tokens = Scanner::new(String::from(source)).scan_tokens().unwrap();
+        tokens.pop();
+        Parser::new(tokens).parse()
+    };
+    let (statements, errors) = without_eof("print 1;");
+    assert_eq!(statements.len(), 1);
+    assert!(errors.is_empty());
+    let (_, errors) = without_eof("print 1 +");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].1, "Expect expression.");
+}
+
+#[test]
+fn large_integers_stay_exact() {
+    let source = "print 9007199254740993; print 9007199254740992 + 1;";
+    assert_eq!(output(source), "9007199254740993\n9007199254740993\n");
+}
+
+#[test]
+fn mixed_arithmetic_promotes_to_float() {
+    let source = "print 1 + 0.5; print 7 / 2; print 6 / 2; print 2 * 3; print 2 == 2.0;";
+    assert_eq!(output(source), "1.5\n3.5\n3\n6\ntrue\n");
+}
+
+#[test]
+fn integer_overflow_falls_back_to_float() {
+    assert_eq!(
+        output("print 9223372036854775807 + 1;"),
+        "9223372036854776000\n"
+    );
+}
+
+fn tokens(source: &str) -> Vec<Token> {
+    Scanner::new(String::from(source)).scan_tokens().unwrap()
+}
+
+#[test]
+fn errors_inside_an_if_body_keep_the_rest_of_the_program() {
+    let source = "if (true) {\n  print ;\n  print 1;\n} else {\n  print 2;\n}\nprint 3;\nvar x = ;";
+    let (statements, errors) = Parser::new(tokens(source)).parse();
+    assert_eq!(statements.len(), 2);
+    let messages: Vec<_> = errors.iter().map(|(_, message)| message.as_str()).collect();
+    assert_eq!(messages, ["Expect expression.", "Expect expression."]);
+}
+
+#[test]
+fn strings_repeat_with_star() {
+    assert_eq!(
+        output("print \"ab\" * 3; print \"ab\" * 0;"),
+        "\"ababab\"\n\"\"\n"
+    );
+    assert_eq!(output("print 2 * 3;"), "6\n");
+}
+
+#[test]
+fn string_repetition_needs_a_non_negative_whole_count() {
+    let message = "Can only repeat a string a non-negative whole number of times.";
+    assert_eq!(error("print \"ab\" * -1;"), message);
+    assert_eq!(error("print \"ab\" * 1.5;"), message);
+}
+
+#[test]
+fn string_repetition_is_capped() {
+    assert_eq!(
+        error("print \"x\" * 9000000000000000000;"),
+        "String repetition too large."
+    );
+    assert_eq!(
+        error("print \"ab\" * 200000000;"),
+        "String repetition too large."
+    );
+}
+
+#[test]
+fn in_finds_substrings() {
+    assert_eq!(
+        output("print \"b\" in \"abc\"; print \"d\" in \"abc\";"),
+        "true\nfalse\n"
+    );
+}
+
+#[test]
+fn in_finds_instance_fields() {
+    let source = "class Box {} var b = Box(); b.k = 1; print \"k\" in b; print \"v\" in b;";
+    assert_eq!(output(source), "true\nfalse\n");
+}
+
+#[test]
+fn in_rejects_other_collections() {
+    assert_eq!(
+        error("print 1 in 2;"),
+        "Can only look in a string or an instance."
+    );
+    assert_eq!(
+        error("print 1 in \"abc\";"),
+        "Can only look for a string in a string."
+    );
+}
+
+#[test]
+fn if_expression_picks_either_branch() {
+    let source = "var a = 3; var b = 5; print if (a > b) a else b; print if (a < b) a else b;";
+    assert_eq!(output(source), "5\n3\n");
+}
+
+#[test]
+fn if_expression_needs_an_else() {
+    assert_eq!(
+        error("var max = if (true) 1;"),
+        "Expect 'else' in an if expression."
+    );
+}
+
+#[test]
+fn if_statement_still_works_without_else() {
+    assert_eq!(output("if (true) print 1;"), "1\n");
+}
+
+#[test]
+fn triple_quoted_strings_keep_quotes_and_newlines() {
+    let source = "print \"\"\"a \"quoted\"\nline\"\"\";";
+    assert_eq!(output(source), "\"a \"quoted\"\nline\"\n");
+}
+
+#[test]
+fn raw_strings_keep_backslashes() {
+    let source = r#"print char_code(char_at(r"a\nb", 1)); print char_at(r"a\nb", 2);
+                    print r"\x41"; print "\x41";"#;
+    assert_eq!(output(source), "92\n\"n\"\n\"\\x41\"\n\"A\"\n");
+}
+
+#[test]
+fn deep_nesting_is_a_parse_error_instead_of_a_stack_overflow() {
+    let parentheses = |depth: usize| format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(output(&parentheses(DEFAULT_MAX_NESTING - 1)), "1\n");
+    assert_eq!(error(&parentheses(100_000)), "Expression nesting too deep.");
+    let chain = vec!["a"; 100_000].join(" = ");
+    assert_eq!(
+        error(&format!("var a; {} = 1;", chain)),
+        "Expression nesting too deep."
+    );
+}
+
+#[test]
+fn operator_chains_are_not_nesting() {
+    let chain = vec!["a"; 250].join(" + ");
+    assert_eq!(output(&format!("var a = 1; print {};", chain)), "250\n");
+}
+
+#[test]
+fn recursion_is_not_limited_by_default() {
+    let recursion = thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(|| output("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); } print f(300);"))
+        .unwrap();
+    assert_eq!(recursion.join().unwrap(), "300\n");
+}
+
+#[test]
+fn object_literal_fields_can_be_read_written_and_added() {
+    let source = "var p = #{ x: 1, y: 2 }; print p.x; p.y = 5; print p.y; p.z = 3; print p.z;";
+    assert_eq!(output(source), "1\n5\n3\n");
+}
+
+#[test]
+fn empty_object_literals_are_instances() {
+    assert_eq!(output("print #{};"), "Object instance\n");
+}
+
+#[test]
+fn var_destructures_instance_fields() {
+    let source = "var point = #{ x: 1, y: 2 }; var {x, y} = point; print x; print y;";
+    assert_eq!(output(source), "1\n2\n");
+}
+
+#[test]
+fn destructuring_a_missing_field_errors() {
+    assert_eq!(
+        error("var point = #{ x: 1 }; var {x, y} = point;"),
+        "Undefined property 'y'."
+    );
+}
+
+#[test]
+fn hex_and_unicode_escapes_decode() {
+    let source = r#"print "\x41" == "A"; print "\u{1F600}"; print "\u{e9}";"#;
+    assert_eq!(output(source), "true\n\"😀\"\n\"é\"\n");
+}
+
+#[test]
+fn invalid_escapes_are_scan_errors_on_their_line() {
+    let output = run(&[], "print 1;\nprint \"\\xZZ\";");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2] Error : Invalid hex escape.\n"
+    );
+    assert_eq!(error(r#"print "\u{110000}";"#), "Invalid unicode escape.");
+}
+
+#[test]
+fn escapes_reject_signed_digits() {
+    assert_eq!(error(r#"print "\x+9";"#), "Invalid hex escape.");
+    assert_eq!(error(r#"print "\u{+41}";"#), "Invalid unicode escape.");
+}
+
+const SQUARE: &str = "class Square {
+    init(side) { this.side = side; }
+    get area { return this.side * this.side; }
+    set width(value) { if (value < 0) value = 0; this.side = value; }
+}
+var square = Square(3);";
+
+#[test]
+fn reading_an_accessor_runs_the_getter() {
+    assert_eq!(output(&format!("{} print square.area;", SQUARE)), "9\n");
+}
+
+#[test]
+fn writing_an_accessor_runs_the_setter() {
+    let source = format!(
+        "{} square.width = 4; print square.area; square.width = -2; print square.area; \
+         print has_field(square, \"width\");",
+        SQUARE
+    );
+    assert_eq!(output(&source), "16\n0\nfalse\n");
+}
+
+#[test]
+fn a_getter_without_a_setter_is_read_only() {
+    assert_eq!(
+        error(&format!("{} square.area = 10;", SQUARE)),
+        "Can't set a read-only property."
+    );
+}
+
+#[test]
+fn super_reads_the_getter_of_the_superclass() {
+    let source = format!(
+        "{} class Tile < Square {{ init(side) {{ super.init(side); }} \
+         get area {{ return super.area + 1; }} }} print Tile(2).area;",
+        SQUARE
+    );
+    assert_eq!(output(&source), "5\n");
+}
+
+#[test]
+fn a_getter_reading_itself_on_another_instance_keeps_its_this() {
+    let source = "class P { init(n, next) { this.n = n; this.next = next; } \
+                  get total { if (this.next == nil) return this.n; return this.next.total + this.n; } \
+                  set last(n) { if (this.next == nil) this.n = n; else this.next.last = n; } } \
+                  var list = P(1, P(2, P(3, nil))); print list.total; \
+                  list.last = 10; print list.total;";
+    assert_eq!(output(source), "6\n13\n");
+}
+
+#[test]
+fn accessors_leave_the_enclosing_scope_alone() {
+    let source = format!(
+        "var area = \"global\"; var width = 1; {} print area; print width;",
+        SQUARE
+    );
+    assert_eq!(output(&source), "\"global\"\n1\n");
+}
+
+#[test]
+fn return_at_the_top_level_is_an_error() {
+    assert_eq!(error("return 1;"), "Can't return from top-level code.");
+    assert_eq!(output("fun f() { return 1; } print f();"), "1\n");
+}
+
+#[test]
+fn methods_dont_replace_functions_of_the_same_name() {
+    let source = "fun helper() { return 1; } fun caller() { return helper(); } \
+                  class A { helper() { return 2; } } print caller(); print helper();";
+    assert_eq!(output(source), "1\n1\n");
+}
+
+#[test]
+fn functions_in_the_same_scope_can_call_each_other() {
+    let functions = "fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); } \
+                     fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); }";
+    assert_eq!(
+        output(&format!("{} print isEven(10); print isOdd(7);", functions)),
+        "true\ntrue\n"
+    );
+    assert_eq!(
+        output(&format!("{{ {} print isEven(9); }}", functions)),
+        "false\n"
+    );
+}
+
+#[test]
+fn arrow_lambdas_return_their_expression() {
+    let source = "fun twice(f, x) { return f(f(x)); } print twice(\\x -> x * x, 3); \
+                  print (\\a, b -> a + b)(1, 2); print (\\ -> 7)(); \
+                  var base = 10; var add = \\x -> x + base; print add(1);";
+    assert_eq!(output(source), "81\n3\n7\n11\n");
+    assert_eq!(
+        error("print \\x y -> x;"),
+        "Expect '->' after lambda parameters."
+    );
+}
+
+#[test]
+fn fluent_methods_chain_on_the_same_instance() {
+    let source = "class Builder { \
+                    fluent width(w) { this.w = w; } \
+                    fluent height(h) { this.h = h; } \
+                    fluent depth(d) { this.d = d; return this.w * this.h * d; } \
+                  } \
+                  var b = Builder(); var same_b = b.width(2).height(3); \
+                  print same(same_b, b); print b.w; print b.h; print b.depth(4); \
+                  class Plain { set(v) { this.v = v; return this; } } \
+                  var p = Plain(); print p.set(1).set(2).v; print same(p.set(3), p);";
+    assert_eq!(output(source), "true\n2\n3\n24\n2\ntrue\n");
+}
+
+#[test]
+fn closures_made_in_a_method_keep_its_this() {
+    let source = "class Box { init(value) { this.value = value; } \
+                    reader() { fun read() { return this.value; } return read; } \
+                    nested() { if (true) { var unused = 0; return \\ -> this.value; } } \
+                  } \
+                  var a = Box(1); var b = Box(2); \
+                  var read_a = a.reader(); var read_b = b.reader(); \
+                  var nested_a = a.nested(); var nested_b = b.nested(); \
+                  print read_a(); print read_b(); print nested_a(); print nested_b();";
+    assert_eq!(output(source), "1\n2\n1\n2\n");
+}
+
+#[test]
+fn a_missing_semicolon_is_reported_on_the_line_it_belongs_on() {
+    let output = run(&[], "var total = 1 +\n  2\nprint total;");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2] Error at '2': Expect ';' after var declaration.\n"
+    );
+}
+
+#[test]
+fn asi_mode_ends_statements_at_line_breaks() {
+    let source = "var total = 1 +\n  2\nprint total\nfun f() {\n  return\n}\nprint f()\n\
+                  { print \"in\" }\ntotal = total\n  * 2; print total";
+    let output = run(&["--asi"], source);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "3\nnil\n\"in\"\n6\n"
+    );
+}
+
+#[test]
+fn semicolons_are_required_without_asi_mode() {
+    let output = run(&[], "print 1\nprint 2;");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 1] Error at '1': Expect ';' after expression.\n"
+    );
+}
+
+#[test]
+fn number_literals_take_prefixes_separators_and_exponents() {
+    let source = "print 0x1F; print 0b1010; print 0o17; print 1_000_000; print 1_0.5; \
+                  print 1.5e3; print 2E-2; print 0xF_F;";
+    assert_eq!(
+        output(source),
+        "31\n10\n15\n1000000\n10.5\n1500\n0.02\n255\n"
+    );
+}
+
+#[test]
+fn malformed_number_literals_each_have_their_own_error() {
+    let cases = [
+        ("print 0x;", "Expect digits after base prefix."),
+        ("print 0xFG;", "Invalid digit 'G' in base 16 number."),
+        ("print 0b102;", "Invalid digit '2' in base 2 number."),
+        ("print 1__0;", "Digit separator '_' must be between digits."),
+        ("print 0x_1;", "Digit separator '_' must be between digits."),
+        ("print 1e+;", "Expect digits in exponent."),
+        ("print 0xFFFFFFFFFFFFFFFFF;", "Number is too large."),
+    ];
+    for (source, message) in cases {
+        assert_eq!(error(source), message, "{}", source);
+    }
+}
+
+// The expressions of the parsed source in the order a visitor reaches them, literals with their
+// value, so folding shows as literals where the source had constants.
+#[derive(Default)]
+struct Shape(Vec<String>);
+
+impl Visitor for Shape {
+    fn visit_expr(&mut self, expr: &dyn Expr) {
+        self.0.push(match expr.kind() {
+            Kind::Literal(value) => format!("literal {}", value),
+            Kind::Variable(_) => String::from("variable"),
+            Kind::Binary(_) => String::from("binary"),
+            Kind::Unary => String::from("unary"),
+            Kind::Grouping => String::from("grouping"),
+            _ => String::from("other"),
+        });
+        walk_expr(self, expr);
+    }
+}
+
+fn shape(source: &str) -> Vec<String> {
+    let (statements, errors) = Parser::new(tokens(source)).parse();
+    assert!(errors.is_empty());
+    let mut shape = Shape::default();
+    for statement in &statements {
+        statement.accept(&mut shape);
+    }
+    shape.0
+}
+
+#[test]
+fn constants_fold_into_arithmetic_and_comparisons() {
+    assert_eq!(
+        shape("const TWO = 2; var x = 3; print x * (TWO + 1); print -TWO < 0; print !true;"),
+        [
+            "literal 2",
+            "literal 3",
+            "binary",
+            "variable",
+            "literal 3",
+            "literal true",
+            "literal false",
+        ]
+    );
+    assert_eq!(
+        error("const TWO = 2; TWO = 3;"),
+        "Can't assign to the constant 'TWO'."
+    );
+}
+
+#[test]
+fn strings_are_not_folded() {
+    let source = "const S = \"ab\"; print S * 3; print S + \"c\";";
+    assert_eq!(
+        shape(source),
+        [
+            "literal \"ab\"",
+            "binary",
+            "literal \"ab\"",
+            "literal 3",
+            "binary",
+            "literal \"ab\"",
+            "literal \"c\"",
+        ]
+    );
+    assert_eq!(output(source), "\"ababab\"\n\"abc\"\n");
+}
+
+#[test]
+fn every_line_ending_style_counts_lines_the_same() {
+    let lines = ["var a = 1;", "var b = \"x", "y\";", "print a.x;"];
+    for ending in ["\n", "\r\n", "\r"] {
+        let output = run(&[], &lines.join(ending));
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "Only instances have properties.\n[line 4]\n    print a.x;\n            ^\n",
+            "{:?}",
+            ending
+        );
+    }
+}
+
+#[test]
+fn nan_is_unequal_to_itself_and_zeroes_are_equal() {
+    let source = "var nan = 0 / 0.0; print nan == nan; print nan != nan; \
+                  print 0.0 == -0.0; print -0.0 == 0; print -0.0;";
+    assert_eq!(output(source), "false\ntrue\ntrue\ntrue\n-0\n");
+}
+
+#[test]
+fn static_blocks_compute_static_fields() {
+    let source =
+        "class Config { static { this.size = 2 * 21; } static size_of() { return this.size; } } \
+                  print Config.size; print Config.size_of();";
+    assert_eq!(output(source), "42\n42\n");
+}
+
+#[test]
+fn static_methods_are_called_on_the_class_and_inherited() {
+    let source = "class Counter { \
+                    static { this.count = 0; this.start = Counter.twice(21); } \
+                    static twice(n) { return n * 2; } \
+                    static bump() { this.count = this.count + 1; return this.count; } \
+                    static() { return \"method\"; } \
+                  } \
+                  print Counter.start; Counter.bump(); print Counter.bump(); \
+                  print Counter().static(); class Sub < Counter {} print Sub.twice(4);";
+    assert_eq!(output(source), "42\n2\n\"method\"\n8\n");
+    assert_eq!(
+        error("class A { static f() {} } A().f();"),
+        "Undefined property 'f'."
+    );
+}
+
+#[test]
+fn static_methods_called_on_a_subclass_have_it_as_this() {
+    let source = "class T { static make() { return this(); } } class U < T {} \
+                  print U.make(); print T.make();";
+    assert_eq!(output(source), "U instance\nT instance\n");
+}
+
+#[test]
+fn empty_nil_and_missing_returns_all_give_nil() {
+    let source = "fun empty() { return; } fun explicit() { return nil; } fun none() {} \
+                  fun unset() { var x; return x; } \
+                  print empty(); print explicit(); print none(); print unset(); \
+                  print empty() == explicit(); class K { init() { return; } } print K();";
+    assert_eq!(output(source), "nil\nnil\nnil\nnil\ntrue\nK instance\n");
+}
+
+#[test]
+fn call_arguments_run_left_to_right_after_the_callee_and_stop_at_an_error() {
+    let (_, printed, diagnostics) = run_capturing(
+        "fun note(x) { print x; return x; } fun f(a, b, c) {} \
+         fun callee() { print \"callee\"; return f; } \
+         callee()(note(\"a\"), nil.x, note(\"c\"));",
+    );
+    assert_eq!(printed, "\"callee\"\n\"a\"\n");
+    assert_eq!(first_error(&diagnostics), "Only instances have properties.");
+    assert_eq!(
+        output(
+            "fun note(x) { print x; return x; } fun f(a, b, c) {} f(note(1), note(2), note(3));"
+        ),
+        "1\n2\n3\n"
+    );
+}
+
+#[test]
+fn functions_can_be_called_before_their_declaration() {
+    let source = "print twice(2); fun twice(n) { return n * 2; } \
+                  { print inner(); fun inner() { return helper() + 1; } fun helper() { return 1; } }";
+    assert_eq!(output(source), "4\n2\n");
+}
+
+#[test]
+fn hoisted_functions_see_variables_declared_before_the_call() {
+    let source = "var x = 1; print f(); fun f() { return x; } \
+                  { var y = 2; print g(); fun g() { return y + x; } } \
+                  print count(3); fun count(n) { if (n == 0) return 0; return n + count(n - 1); }";
+    assert_eq!(output(source), "1\n3\n6\n");
+}
+
+#[test]
+fn hoisted_functions_in_strict_mode_are_declared_from_the_start_of_the_block() {
+    let mut lox = Lox::new();
+    lox.set_strict(true);
+    let source = "var x = 1; f(); fun f() { return x; }";
+    assert!(lox.run(String::from(source), true).is_empty());
+}
+
+#[test]
+fn a_final_expression_without_semicolon_is_returned() {
+    let source = "fun double(x) { x * 2 } print double(4); \
+                  fun statement(x) { x * 2; } print statement(4); \
+                  fun sign(x) { if (x > 0) return \"positive\"; \"negative\" } \
+                  print sign(1); print sign(-1); \
+                  class Square { area() { 3 * 3 } } print Square().area();";
+    assert_eq!(output(source), "8\nnil\n\"positive\"\n\"negative\"\n9\n");
+}
+
+#[test]
+fn only_function_bodies_can_leave_out_the_last_semicolon() {
+    assert_eq!(error("{ 1 + 2 }"), "Expect ';' after expression.");
+    assert_eq!(
+        error("fun f() { var a = 1 }"),
+        "Expect ';' after var declaration."
+    );
+    assert_eq!(error("fun f() { { 1 } }"), "Expect ';' after expression.");
+}
+
+#[test]
+fn chained_assignment_gives_every_target_the_value() {
+    let source = "var a; var b; var c; a = b = c = 0; print a; print b; print c; \
+                  var box = #{}; var d = a = box.x = 5; print a; print box.x; print d; \
+                  print (c = 7) + 1; print c;";
+    assert_eq!(output(source), "0\n0\n0\n5\n5\n5\n8\n7\n");
+}
+
+#[test]
+fn lazy_constants_run_their_initializer_once_on_first_read() {
+    let source = "var counter = #{ runs: 0 }; \
+                  fun compute() { counter.runs = counter.runs + 1; return 42; } \
+                  lazy const ANSWER = compute(); print counter.runs; \
+                  print ANSWER; print ANSWER + 1; \
+                  fun read() { return ANSWER; } print read(); print counter.runs;";
+    assert_eq!(output(source), "0\n42\n43\n42\n1\n");
+    assert_eq!(
+        error("lazy const A = 1; A = 2;"),
+        "Can't assign to the constant 'A'."
+    );
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{error, output};
+
+#[test]
+fn import_runs_a_file_relative_to_the_importing_file() {
+    assert_eq!(output("import \"tests/modules/main.lox\";"), "42\n");
+}
+
+#[test]
+fn import_defines_into_the_globals() {
+    let source = "import \"tests/modules/helper.lox\"; print double(2);";
+    assert_eq!(output(source), "4\n");
+}
+
+#[test]
+fn import_runs_each_file_once_even_in_a_cycle() {
+    let source = "import \"tests/modules/cycle_a.lox\"; import \"tests/modules/cycle_b.lox\";";
+    assert_eq!(output(source), "\"b\"\n\"a\"\n");
+}
+
+#[test]
+fn import_reports_missing_files() {
+    assert_eq!(
+        error("import \"tests/modules/missing.lox\";"),
+        "Could not find module 'tests/modules/missing.lox'."
+    );
+}
+
+#[test]
+fn import_expression_gives_a_module_namespace() {
+    let source =
+        "var math = import \"tests/modules/math.lox\"; print math.square(4); print math.pi;";
+    assert_eq!(output(source), "16\n3\n");
+}
+
+#[test]
+fn module_members_stay_out_of_the_globals() {
+    let source = "var math = import \"tests/modules/math.lox\"; print square;";
+    assert_eq!(error(source), "Undefined variable 'square'.");
+}
+
+#[test]
+fn undefined_module_members_error() {
+    let source = "var math = import \"tests/modules/math.lox\"; print math.cube;";
+    assert_eq!(
+        error(source),
+        "Undefined property 'cube' in module 'tests/modules/math.lox'."
+    );
+}
@@ -0,0 +1,543 @@
+mod common;
+
+use common::{error, output, run};
+
+#[test]
+fn write_adds_no_newline() {
+    assert_eq!(output("write(1); write(2);"), "12");
+}
+
+#[test]
+fn printf_fills_placeholders_in_order() {
+    assert_eq!(output("printf(\"{} + {} = {}\", 1, 2, 3);"), "1 + 2 = 3");
+}
+
+#[test]
+fn printf_rejects_a_placeholder_count_mismatch() {
+    assert_eq!(
+        error("printf(\"{} and {}\", 1);"),
+        "Format has 2 placeholder(s) but got 1 argument(s)."
+    );
+}
+
+#[test]
+fn breakpoint_does_nothing_unless_allowed() {
+    let source = "fun f(x) { var y = breakpoint(); return y == nil and x == 1; } print f(1);";
+    assert_eq!(output(source), "true\n");
+}
+
+#[test]
+fn has_field_follows_set_and_delete_field() {
+    let source = "class Record {} var r = Record(); r.name = 1; \
+                  print has_field(r, \"name\"); print delete_field(r, \"name\"); \
+                  print has_field(r, \"name\"); print delete_field(r, \"name\");";
+    assert_eq!(output(source), "true\ntrue\nfalse\nfalse\n");
+}
+
+#[test]
+fn field_names_must_be_strings() {
+    let source = "class Record {} has_field(Record(), 1);";
+    assert_eq!(error(source), "Field name must be a string.");
+    let source = "class Record {} delete_field(Record(), nil);";
+    assert_eq!(error(source), "Field name must be a string.");
+}
+
+#[test]
+fn invoke_matches_a_direct_call() {
+    let source =
+        "class Calc { init(base) { this.base = base; } add(a, b) { return this.base + a + b; } } \
+                  var calc = Calc(10); print invoke(calc, \"add\", 1, 2) == calc.add(1, 2); \
+                  print invoke(calc, \"add\", 1, 2);";
+    assert_eq!(output(source), "true\n13\n");
+}
+
+#[test]
+fn invoke_rejects_missing_methods_and_wrong_arity() {
+    let class = "class Calc { add(a, b) { return a + b; } } var calc = Calc();";
+    assert_eq!(
+        error(&format!("{} invoke(calc, \"sub\", 1, 2);", class)),
+        "Undefined method 'sub'."
+    );
+    assert_eq!(
+        error(&format!("{} invoke(calc, \"add\", 1);", class)),
+        "Expected 2 argument(s) but got 1."
+    );
+}
+
+#[test]
+fn char_natives_handle_ascii_and_multi_byte_characters() {
+    let source = "print char_at(\"hé!\", 1); print char_at(\"hé!\", 2); \
+                  print char_code(\"a\"); print char_code(\"é\"); \
+                  print from_char_code(97); print from_char_code(233);";
+    assert_eq!(output(source), "\"é\"\n\"!\"\n97\n233\n\"a\"\n\"é\"\n");
+}
+
+#[test]
+fn char_natives_reject_bad_input() {
+    assert_eq!(error("char_at(\"hé\", 2);"), "Index out of range.");
+    assert_eq!(
+        error("char_code(\"ab\");"),
+        "Expect a single character string."
+    );
+    assert_eq!(error("from_char_code(55296);"), "Invalid character code.");
+}
+
+#[test]
+fn trim_strips_surrounding_whitespace() {
+    assert_eq!(output("print trim(\"  a b \n\t\");"), "\"a b\"\n");
+}
+
+#[test]
+fn replace_changes_every_occurrence() {
+    assert_eq!(
+        output("print replace(\"a-b-c\", \"-\", \"+\");"),
+        "\"a+b+c\"\n"
+    );
+    assert_eq!(
+        error("replace(\"abc\", \"\", \"x\");"),
+        "Can't replace an empty string."
+    );
+}
+
+#[test]
+fn starts_with_and_ends_with_check_the_edges() {
+    let source = "print starts_with(\"rilox\", \"ri\"); print starts_with(\"rilox\", \"lox\"); \
+                  print ends_with(\"rilox\", \"lox\"); print ends_with(\"rilox\", \"ri\");";
+    assert_eq!(output(source), "true\nfalse\ntrue\nfalse\n");
+    assert_eq!(
+        error("starts_with(\"rilox\", 1);"),
+        "Arguments must be strings."
+    );
+}
+
+#[test]
+fn edit_distance_counts_character_edits() {
+    let source = "print edit_distance(\"kitten\", \"kitten\"); \
+                  print edit_distance(\"cat\", \"cut\"); \
+                  print edit_distance(\"naïve\", \"naive\"); print edit_distance(\"\", \"日本\");";
+    assert_eq!(output(source), "0\n1\n1\n2\n");
+    assert_eq!(
+        error("edit_distance(\"a\", 1);"),
+        "Arguments must be strings."
+    );
+}
+
+#[test]
+fn to_number_gives_nil_for_anything_but_a_number() {
+    let source = "print to_number(\"42\"); print to_number(\" 3.5 \"); print to_number(\"0\"); \
+                  print to_number(\"abc\"); print to_number(\"1e5\");";
+    assert_eq!(output(source), "42\n3.5\n0\nnil\nnil\n");
+}
+
+#[test]
+fn is_number_string_matches_to_number() {
+    let source = "print is_number_string(\" -7 \"); print is_number_string(\"abc\");";
+    assert_eq!(output(source), "true\nfalse\n");
+}
+
+#[test]
+fn clamp_bounds_the_value_to_the_range() {
+    let source = "print clamp(-5, 0, 10); print clamp(3.5, 0, 10); print clamp(15, 0, 10);";
+    assert_eq!(output(source), "0\n3.5\n10\n");
+}
+
+#[test]
+fn clamp_rejects_an_empty_range() {
+    assert_eq!(
+        error("clamp(1, 10, 0);"),
+        "Lower bound must not exceed upper bound."
+    );
+    assert_eq!(error("clamp(\"1\", 0, 10);"), "Arguments must be numbers.");
+}
+
+#[test]
+fn lerp_interpolates_between_the_ends() {
+    let source = "print lerp(2, 10, 0); print lerp(2, 10, 0.5); print lerp(2, 10, 1);";
+    assert_eq!(output(source), "2\n6\n10\n");
+}
+
+#[test]
+fn format_number_gives_fixed_decimals() {
+    let source = "print format_number(3.14159, 2); print format_number(0.05, 3); \
+                  print format_number(1234567.891, 1); print format_number(0.5, 0);";
+    assert_eq!(
+        output(source),
+        "\"3.14\"\n\"0.050\"\n\"1234567.9\"\n\"1\"\n"
+    );
+}
+
+#[test]
+fn group_number_separates_thousands() {
+    let source = "print group_number(1234567, \",\"); print group_number(-9876543.25, \",\"); \
+                  print group_number(1234.5678, \" \"); print group_number(-999, \",\");";
+    assert_eq!(
+        output(source),
+        "\"1,234,567\"\n\"-9,876,543.25\"\n\"1 234.5678\"\n\"-999\"\n"
+    );
+    assert_eq!(
+        error("group_number(\"1\", \",\");"),
+        "Value must be a finite number."
+    );
+}
+
+#[test]
+fn format_number_rejects_negative_decimals() {
+    assert_eq!(
+        error("format_number(1, -1);"),
+        "Decimals must be a non-negative whole number."
+    );
+}
+
+#[test]
+fn format_number_caps_the_decimals() {
+    assert_eq!(output("print format_number(1, 100);").len(), 105);
+    assert_eq!(
+        error("format_number(1, 100000000000);"),
+        "Decimals must be at most 100."
+    );
+}
+
+#[test]
+fn diff_reports_the_path_of_the_first_difference() {
+    let source = "class P {} \
+                  var a = P(); a.x = 1; a.inner = P(); a.inner.y = 2; \
+                  var b = P(); b.x = 1; b.inner = P(); b.inner.y = 3; \
+                  print diff(a, b); print diff(1, \"1\");";
+    assert_eq!(
+        output(source),
+        "\"value.inner.y: 2 vs 3\"\n\"value: 1 vs \"1\"\"\n"
+    );
+}
+
+#[test]
+fn diff_of_equal_values_is_nil() {
+    let source = "class P {} var a = P(); a.x = 1; var b = P(); b.x = 1; \
+                  print diff(a, a); print diff(a, b); print diff(1, 1);";
+    assert_eq!(output(source), "nil\nnil\nnil\n");
+}
+
+#[test]
+fn construct_instantiates_a_class_picked_at_run_time() {
+    let source =
+        "class Circle { init(r) { this.r = r; } } class Square { init(s) { this.s = s; } } \
+                  fun make(round, size) { return construct(if (round) Circle else Square, size); } \
+                  print make(true, 2).r; print make(false, 3).s; print make(true, 1);";
+    assert_eq!(output(source), "2\n3\nCircle instance\n");
+}
+
+#[test]
+fn construct_rejects_non_classes_and_wrong_arity() {
+    assert_eq!(error("construct(1);"), "Can only construct classes.");
+    assert_eq!(
+        error("class A { init(a, b) {} } construct(A, 1);"),
+        "Expected 2 argument(s) but got 1."
+    );
+    assert_eq!(
+        error("class A {} construct(A, 1);"),
+        "Expected 0 argument(s) but got 1."
+    );
+}
+
+#[test]
+fn script_path_is_nil_without_a_script() {
+    assert_eq!(
+        output("print script_path(); print script_dir();"),
+        "nil\nnil\n"
+    );
+}
+
+#[test]
+fn same_compares_instances_by_identity() {
+    let source = "var a = #{ x: 1 }; var b = #{ x: 1 }; var c = a; \
+                  print same(a, b); print same(a, c); print diff(a, b);";
+    assert_eq!(output(source), "false\ntrue\nnil\n");
+}
+
+#[test]
+fn same_compares_scalars_by_value() {
+    let source = "class A {} print same(1, 1); print same(\"a\", \"a\"); print same(1, 2); \
+                  print same(A, A); print same(clock, clock);";
+    assert_eq!(output(source), "true\ntrue\nfalse\ntrue\ntrue\n");
+}
+
+#[test]
+fn print_table_aligns_columns_and_leaves_missing_fields_blank() {
+    let source =
+        "var a = #{ name: \"ada\", age: 36 }; var b = #{ name: \"grace\", lang: \"cobol\" }; \
+                  print_table(a, b);";
+    assert_eq!(
+        output(source),
+        concat!(
+            "age  lang   name\n",
+            "---  ----   ----\n",
+            "36          ada\n",
+            "     cobol  grace\n",
+        )
+    );
+}
+
+#[test]
+fn print_table_rows_must_be_instances() {
+    assert_eq!(error("print_table(1);"), "Rows must be instances.");
+}
+
+#[test]
+fn arity_and_name_of_describe_callables() {
+    let source = "fun add(a, b) { return a + b; } class Point { init(x, y) {} } \
+                  print arity(add); print name_of(add); print arity(Point); print name_of(Point); \
+                  print arity(clock); print name_of(clock);";
+    assert_eq!(output(source), "2\n\"add\"\n2\n\"Point\"\n0\n\"clock\"\n");
+}
+
+#[test]
+fn arity_and_name_of_reject_other_values() {
+    assert_eq!(
+        error("arity(1);"),
+        "Can only get the arity of functions and classes."
+    );
+    assert_eq!(
+        error("name_of(nil);"),
+        "Can only get the name of functions and classes."
+    );
+}
+
+#[test]
+fn bind_and_partial_fix_leading_arguments() {
+    let source = "fun add(a, b) { return a + b; } var add10 = bind(add, 10); \
+                  print add10(5); print arity(add10); \
+                  fun sum(a, b, c) { return a + b + c; } var rest = partial(sum, 1, 2); \
+                  print rest(3); print arity(rest);";
+    assert_eq!(output(source), "15\n1\n6\n1\n");
+}
+
+#[test]
+fn bind_checks_the_callable_and_argument_count() {
+    assert_eq!(
+        error("partial(1, 2);"),
+        "Can only bind arguments to functions and classes."
+    );
+    assert_eq!(
+        error("fun f(a) {} partial(f, 1, 2);"),
+        "Can't bind 2 argument(s) to f taking 1."
+    );
+    assert_eq!(
+        error("fun add(a, b) {} bind(add, 1)(1, 2);"),
+        "Expected 1 argument(s) but got 2."
+    );
+}
+
+#[test]
+fn memoize_caches_results_of_recursive_functions() {
+    let source = "var stats = #{ calls: 0 }; \
+                  fun fib(n) { stats.calls = stats.calls + 1; if (n < 2) return n; \
+                  return fib(n - 1) + fib(n - 2); } \
+                  fib = memoize(fib); \
+                  print fib(30); print stats.calls; print fib(30); print stats.calls; print fib;";
+    assert_eq!(
+        output(source),
+        "832040\n31\n832040\n31\n<memoized fn fib>\n"
+    );
+}
+
+#[test]
+fn memoize_rejects_unhashable_arguments() {
+    assert_eq!(error("memoize(1);"), "Can only memoize functions.");
+    assert_eq!(
+        error("fun id(x) { return x; } memoize(id)(#{});"),
+        "Memoized functions only take nil, booleans, numbers and strings."
+    );
+}
+
+#[test]
+fn hash_is_structural_and_ignores_field_order() {
+    let source =
+        "var a = #{ x: 1, inner: #{ y: \"s\" } }; var b = #{ inner: #{ y: \"s\" }, x: 1 }; \
+                  print hash(a) == hash(b); print hash(1) == hash(1.0); \
+                  print hash(a) == hash(#{ x: 2, inner: #{ y: \"s\" } });";
+    assert_eq!(output(source), "true\ntrue\nfalse\n");
+}
+
+#[test]
+fn hash_is_the_same_in_every_run() {
+    // Pinned, a per-process seed would change these.
+    assert_eq!(
+        output("print hash(\"abc\"); print hash(#{ a: 1, b: 2 });"),
+        "325809897275227501\n3832116072956095476\n"
+    );
+}
+
+#[test]
+fn hash_rejects_functions_and_cycles() {
+    assert_eq!(
+        error("hash(clock);"),
+        "Can only hash nil, booleans, numbers, strings, bytes and instances."
+    );
+    assert_eq!(
+        error("var a = #{}; a.self = a; hash(a);"),
+        "Can't hash an instance that contains itself."
+    );
+}
+
+#[test]
+fn nan_and_both_zeroes_are_each_a_single_key() {
+    let source = "var nan = 0 / 0.0; \
+                  print hash(nan) == hash(nan); print hash(0.0) == hash(-0.0); \
+                  var calls = #{ n: 0 }; fun f(x) { calls.n = calls.n + 1; return x; } \
+                  var cached = memoize(f); cached(nan); cached(nan); cached(0.0); cached(-0.0); \
+                  print calls.n;";
+    assert_eq!(output(source), "true\ntrue\n2\n");
+}
+
+#[test]
+fn get_path_walks_nested_instances() {
+    let source = "var config = #{ a: #{ b: #{ c: 3 } } }; \
+                  print get_path(config, \"a.b.c\"); print get_path(config, \"a.x.c\"); \
+                  print get_path(config, \"a.x\", 7); print get_path(1, \"a\");";
+    assert_eq!(output(source), "3\nnil\n7\nnil\n");
+}
+
+#[test]
+fn set_path_creates_the_missing_instances() {
+    let source = "var config = #{ a: #{} }; set_path(config, \"a.n.m\", 5); \
+                  print config.a.n.m; print get_path(config, \"a.n.m\");";
+    assert_eq!(output(source), "5\n5\n");
+    assert_eq!(
+        error("set_path(#{ a: 1 }, \"a.b\", 2);"),
+        "Field 'a' on the path is not an instance."
+    );
+    assert_eq!(
+        error("get_path(#{}, \"a..b\");"),
+        "Path can't have empty segments."
+    );
+}
+
+#[test]
+fn bytes_are_read_from_files_and_indexed() {
+    let source = "var blob = read_file_bytes(\"tests/scripts/blob.bin\"); \
+                  print blob; print byte_at(blob, 0); print byte_at(blob, 1); print byte_at(blob, 3);";
+    let output = run(&[], source);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "<bytes 4>\n0\n255\n128\n"
+    );
+    assert_eq!(
+        error("byte_at(string_to_bytes(\"a\"), 1);"),
+        "Index out of range."
+    );
+}
+
+#[test]
+fn reading_files_needs_the_host_to_allow_io() {
+    assert_eq!(
+        error("read_file_bytes(\"tests/scripts/blob.bin\");"),
+        "I/O is disabled."
+    );
+}
+
+#[test]
+fn bytes_round_trip_through_strings() {
+    let source =
+        "var bytes = string_to_bytes(\"h\\u{e9}\"); print bytes; print byte_at(bytes, 1); \
+                  print bytes_to_string(bytes) == \"h\\u{e9}\";";
+    assert_eq!(output(source), "<bytes 3>\n195\ntrue\n");
+    assert_eq!(
+        error("bytes_to_string(base64_decode(\"AP8=\"));"),
+        "Bytes are not valid UTF-8 at byte 1."
+    );
+}
+
+#[test]
+fn base64_and_hex_round_trip() {
+    let source = "print base64_encode(\"hello\"); print base64_encode(\"a\"); \
+                  print bytes_to_string(base64_decode(base64_encode(\"hello!?\"))); \
+                  print hex_encode(\"hi\"); print bytes_to_string(hex_decode(hex_encode(\"hi\"))); \
+                  print byte_at(hex_decode(\"FFa0\"), 1);";
+    assert_eq!(
+        output(source),
+        "\"aGVsbG8=\"\n\"YQ==\"\n\"hello!?\"\n\"6869\"\n\"hi\"\n160\n"
+    );
+}
+
+#[test]
+fn malformed_encodings_are_errors() {
+    let cases = [
+        (
+            "base64_decode(\"a\");",
+            "Base64 length must be a multiple of 4.",
+        ),
+        ("base64_decode(\"a$==\");", "Invalid base64 character."),
+        ("base64_decode(\"YQ=a\");", "Invalid base64 character."),
+        ("hex_decode(\"abc\");", "Hex length must be even."),
+        ("hex_decode(\"+f\");", "Invalid hex digit."),
+        ("hex_decode(\"-1\");", "Invalid hex digit."),
+        ("hex_decode(\"\u{e9}\");", "Invalid hex digit."),
+    ];
+    for (source, message) in cases {
+        assert_eq!(error(source), message, "{}", source);
+    }
+}
+
+#[test]
+fn assert_throws_gives_the_error_message() {
+    let source = "fun boom() { nil.x; } print assert_throws(boom); \
+                  print assert_throws(\\ -> \"a\" - 1);";
+    assert_eq!(
+        output(source),
+        "\"Only instances have properties.\"\n\"Can only subtract two numbers.\"\n"
+    );
+}
+
+#[test]
+fn assert_throws_fails_when_nothing_is_raised() {
+    assert_eq!(
+        error("fun fine() { return 1; } assert_throws(fine);"),
+        "Expected an error but none was raised."
+    );
+    assert_eq!(error("assert_throws(1);"), "Argument must be a function.");
+    assert_eq!(
+        error("fun f(a) {} assert_throws(f);"),
+        "Expected 1 argument(s) but got 0."
+    );
+}
+
+#[test]
+fn class_of_and_superclass_of_walk_the_hierarchy() {
+    let source = "class Animal {} class Dog < Animal {} class Puppy < Dog {} var pup = Puppy(); \
+                  var kind = class_of(pup); print same(kind, Puppy); \
+                  print same(superclass_of(kind), Dog); \
+                  print same(superclass_of(superclass_of(kind)), Animal); \
+                  print superclass_of(Animal); print same(class_of(Dog()), class_of(Dog()));";
+    assert_eq!(output(source), "true\ntrue\ntrue\nnil\ntrue\n");
+}
+
+#[test]
+fn class_of_and_superclass_of_reject_other_values() {
+    assert_eq!(
+        error("class_of(1);"),
+        "Can only get the class of an instance."
+    );
+    assert_eq!(
+        error("class A {} superclass_of(A());"),
+        "Can only get the superclass of a class."
+    );
+}
+
+#[test]
+fn responds_to_finds_own_and_inherited_methods() {
+    let source = "class A { f() {} } class B < A { g() {} } var b = B(); \
+                  print responds_to(b, \"f\"); print responds_to(b, \"g\"); \
+                  print responds_to(b, \"h\"); print responds_to(A(), \"g\"); \
+                  print responds_to(\"abc\", \"f\"); print responds_to(nil, \"f\");";
+    assert_eq!(output(source), "true\ntrue\nfalse\nfalse\nfalse\nfalse\n");
+    assert_eq!(
+        error("class A {} responds_to(A(), 1);"),
+        "Method name must be a string."
+    );
+}
+
+#[test]
+fn clock_gives_seconds_since_the_epoch() {
+    let source = "var start = clock(); var end = clock(); \
+                  print start > 1000000000; print end >= start; print start - start;";
+    assert_eq!(output(source), "true\ntrue\n0\n");
+}
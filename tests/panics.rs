@@ -0,0 +1,44 @@
+// On its own, the panic hook is shared by every test in the same binary.
+
+use rilox::interpreter::Interpreter;
+use rilox::parser::Parser;
+use rilox::runtime::Trace;
+use rilox::scanner::Scanner;
+use std::panic;
+use std::sync::{Arc, Mutex};
+
+fn panic_in(interpreter: &mut Interpreter, reason: &'static str) -> String {
+    interpreter.set_trace(Some(Trace::new(move |_, _| panic!("{}", reason))));
+    let tokens = Scanner::new(String::from("print 1;"))
+        .scan_tokens()
+        .unwrap();
+    let (statements, _) = Parser::new(tokens).parse();
+    interpreter.interpret(statements).unwrap_err().0
+}
+
+#[test]
+fn panics_become_errors_and_reach_the_hook_unless_quiet() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let record = Arc::clone(&seen);
+    panic::set_hook(Box::new(move |info| {
+        if let Some(reason) = info.payload().downcast_ref::<String>() {
+            record.lock().unwrap().push(reason.clone());
+        }
+    }));
+
+    let mut interpreter = Interpreter::new();
+    assert_eq!(panic_in(&mut interpreter, "loud"), "Internal error: loud.");
+    interpreter.set_quiet_panics(true);
+    assert_eq!(
+        panic_in(&mut interpreter, "quiet"),
+        "Internal error: quiet."
+    );
+    interpreter.set_quiet_panics(false);
+    assert_eq!(
+        panic_in(&mut interpreter, "loud again"),
+        "Internal error: loud again."
+    );
+
+    let _ = panic::take_hook();
+    assert_eq!(*seen.lock().unwrap(), ["loud", "loud again"]);
+}
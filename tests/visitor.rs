@@ -0,0 +1,43 @@
+use rilox::expr::{Expr, Kind};
+use rilox::parser::Parser;
+use rilox::scanner::Scanner;
+use rilox::visitor::{walk_expr, Visitor};
+
+#[derive(Default)]
+struct CallCounter {
+    calls: usize,
+}
+
+impl Visitor for CallCounter {
+    fn visit_expr(&mut self, expr: &dyn Expr) {
+        if let Kind::Call(_) = expr.kind() {
+            self.calls += 1;
+        }
+        walk_expr(self, expr);
+    }
+}
+
+fn count_calls(source: &str) -> usize {
+    let tokens = Scanner::new(String::from(source)).scan_tokens().unwrap();
+    let (statements, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut counter = CallCounter::default();
+    for statement in &statements {
+        statement.accept(&mut counter);
+    }
+    counter.calls
+}
+
+#[test]
+fn visitor_counts_calls_everywhere_in_the_tree() {
+    let source = "fun f(x) { return x; }
+                  print f(f(1));
+                  if (f(true)) { var y = f(2) + f(3); }
+                  class A { m() { while (f(false)) {} } }";
+    assert_eq!(count_calls(source), 6);
+}
+
+#[test]
+fn visitor_finds_no_calls_without_any() {
+    assert_eq!(count_calls("var a = 1 + 2; print a;"), 0);
+}